@@ -0,0 +1,24 @@
+//! Timing harness shared by the benches.
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 50;
+
+/// Runs `f` once to warm up, then `ITERATIONS` times, and prints the mean time and the
+/// throughput over `bytes`.
+pub fn measure<F: FnMut()>(name: &str, bytes: usize, mut f: F) {
+    f();
+
+    let mut total = Duration::default();
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f();
+        total += start.elapsed();
+    }
+
+    let per_iter = total / ITERATIONS;
+    let mb_per_sec = bytes as f64 / per_iter.as_secs_f64() / (1024.0 * 1024.0);
+    println!(
+        "{:10} {:>12?}/iter {:>10.2} MiB/s",
+        name, per_iter, mb_per_sec
+    );
+}
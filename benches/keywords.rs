@@ -0,0 +1,57 @@
+//! Scan throughput on keyword heavy sources, plus the keyword lookup on its own against
+//! the `HashMap` the scanner used to hash every identifier into.
+//! Run with `cargo bench --bench keywords`.
+mod common;
+
+use common::measure;
+use rlox::scanner::{Scanner, ScannerConfig};
+use shared::source_map::SourceMap;
+use shared::tokens::{self, TokenType, KEYWORDS};
+use std::collections::HashMap;
+use std::hint::black_box;
+
+const SNIPPET: &str = "
+class Node < Base { init(value) { this.value = value; super.init(); } }
+fun walk(node) {
+  while (node != nil and node.next != nil or false) {
+    if (true) print node; else return nil;
+    for (var i = 0; i < 1; i = i + 1) var x = this;
+  }
+  return fun_ny and classy or nothing_else;
+}
+";
+
+fn main() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("bench.lox", SNIPPET.repeat(2000));
+    let src = sources.src(file);
+
+    measure("scan", src.len(), || {
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+        black_box(toks);
+    });
+
+    let words: Vec<&str> = src
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|w| !w.is_empty())
+        .collect();
+    let words_len = words.iter().map(|w| w.len()).sum();
+
+    measure("match", words_len, || {
+        for w in words.iter() {
+            black_box(tokens::keyword(black_box(w)));
+        }
+    });
+
+    let map: HashMap<String, TokenType> = KEYWORDS
+        .iter()
+        .map(|(txt, tt)| (txt.to_string(), tt.clone()))
+        .collect();
+    measure("hashmap", words_len, || {
+        for w in words.iter() {
+            black_box(map.get(black_box(*w)).cloned());
+        }
+    });
+}
@@ -0,0 +1,41 @@
+//! Relexing after a one char edit near the end, and undoing it, against scanning the whole source again,
+//! for growing sources. Relexing should stay flat while the full scan grows.
+//! Run with `cargo bench --bench relex`.
+mod common;
+
+use common::measure;
+use rlox::incremental::{relex, Edit, Lexed};
+use rlox::scanner::{Scanner, ScannerConfig};
+use shared::source_map::SourceMap;
+use std::hint::black_box;
+
+const LINE: &str = "var a = b + c; // x\n";
+
+fn main() {
+    for lines in [8_000, 32_000] {
+        let mut sources = SourceMap::new();
+        let file = sources.add("bench.lox", LINE.repeat(lines));
+        let src = sources.src(file);
+        let at = src.len() - 12;
+        let edit = Edit::new(at..at + 1, "bee");
+        let new_src = edit.apply(src);
+        let mut lexed = Lexed::scan(Scanner::new(&sources, file, ScannerConfig::default()));
+        // puts the source back so every iteration relexes the same edit
+        let undo = Edit::new(at..at + 3, "b");
+
+        println!("{} lines", lines);
+        measure("scan", src.len(), || {
+            black_box(Lexed::scan(Scanner::new(
+                &sources,
+                file,
+                ScannerConfig::default(),
+            )));
+        });
+        let config = ScannerConfig::default();
+        measure("relex x2", src.len(), || {
+            relex(&mut lexed, &new_src, &edit, sources.symbols(), &config);
+            relex(&mut lexed, src, &undo, sources.symbols(), &config);
+            black_box(&lexed);
+        });
+    }
+}
@@ -0,0 +1,51 @@
+//! Compares scanning into tokens borrowed from the source against turning every token
+//! into an owned one, which is what the scanner used to do.
+//! Run with `cargo bench --bench scan`.
+mod common;
+
+use common::measure;
+use rlox::scanner::{Scanner, ScannerConfig};
+use shared::source_map::SourceMap;
+use std::hint::black_box;
+
+const SNIPPET: &str = r#"
+class Breakfast {
+  cook() {
+    print "Eggs a-fryin'!";
+  }
+
+  serve(who) {
+    print "Enjoy your breakfast, " + who + ".";
+  }
+}
+
+fun fib(n) {
+  if (n <= 1) return n;
+  return fib(n - 2) + fib(n - 1);
+}
+
+var average = (min + max) / 2;
+for (var i = 0; i < 10; i = i + 1) {
+  print i * 3.14;
+}
+"#;
+
+fn main() {
+    let mut sources = SourceMap::new();
+    let file = sources.add("bench.lox", SNIPPET.repeat(2000));
+    let src = sources.src(file);
+
+    measure("borrowed", src.len(), || {
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+        black_box(toks);
+    });
+
+    measure("owned", src.len(), || {
+        let toks: Vec<_> = Scanner::new(&sources, file, ScannerConfig::default())
+            .map(|t| t.unwrap().into_owned())
+            .collect();
+        black_box(toks);
+    });
+}
@@ -0,0 +1,40 @@
+// Generates the syntax tree modules from `grammar.ast` into `OUT_DIR/ast.rs`, which
+// `gen` includes, so the code always matches the grammar. `cyclic.ast` is generated
+// the same way for the tests, its bases hold each other.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+const GRAMMARS: [(&str, &str); 2] = [("grammar.ast", "ast.rs"), ("cyclic.ast", "cyclic.rs")];
+
+fn main() {
+    for (grammar, out) in GRAMMARS.iter() {
+        generate(grammar, out);
+    }
+}
+
+fn generate(grammar_file: &str, out_file: &str) {
+    println!("cargo:rerun-if-changed={}", grammar_file);
+
+    let src = fs::read_to_string(grammar_file)
+        .unwrap_or_else(|e| panic!("Could not read '{}': {}", grammar_file, e));
+
+    let grammar = match tools::grammar::parse(&src) {
+        Ok(grammar) => grammar,
+        Err(errors) => {
+            let errors: Vec<String> = errors
+                .iter()
+                .map(|e| format!("{}:{}: {}", grammar_file, e.line, e.message))
+                .collect();
+            panic!("Invalid grammar\n{}", errors.join("\n"));
+        }
+    };
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join(out_file);
+    let mut f = BufWriter::new(File::create(&out).unwrap());
+    if let Err(e) = tools::define_inline_mods(&mut f, &grammar) {
+        panic!("Could not generate '{}': {}", out.display(), e);
+    }
+}
@@ -0,0 +1,90 @@
+//! Short helpers for writing syntax trees by hand, mostly for test fixtures:
+//! `binary(unary("-", num(123)), "*", group(float(45.67)))`.
+//! Tokens made here only know they are on line 1.
+
+use crate::gen::expr::Expr;
+use crate::gen::stmt::Stmt;
+use shared::tokens::{self, LiteralValue, Token, TokenType};
+use std::rc::Rc;
+
+/// A token for `lexeme`, anything that isn't an operator or a keyword is an identifier.
+pub fn tok(lexeme: &str) -> Token<'static> {
+    use TokenType::*;
+
+    let tt = match lexeme {
+        "(" => LEFT_PAREN,
+        ")" => RIGHT_PAREN,
+        "{" => LEFT_BRACE,
+        "}" => RIGHT_BRACE,
+        "," => COMMA,
+        "." => DOT,
+        "-" => MINUS,
+        "+" => PLUS,
+        ";" => SEMICOLON,
+        "/" => SLASH,
+        "*" => STAR,
+        "!" => BANG,
+        "!=" => BANG_EQUAL,
+        "=" => EQUAL,
+        "==" => EQUAL_EQUAL,
+        ">" => GREATER,
+        ">=" => GREATER_EQUAL,
+        "<" => LESS,
+        "<=" => LESS_EQUAL,
+        _ => tokens::keyword(lexeme).unwrap_or(IDENTIFIER),
+    };
+    Token::new(tt, lexeme.to_string(), 1, LiteralValue::NoVal)
+}
+
+pub fn num(n: i64) -> Expr {
+    Expr::literal(LiteralValue::Num(n))
+}
+
+pub fn float(f: f64) -> Expr {
+    Expr::literal(LiteralValue::NumFloat(f))
+}
+
+pub fn string(s: &str) -> Expr {
+    Expr::literal(LiteralValue::Str(s.to_string().into()))
+}
+
+pub fn nil() -> Expr {
+    Expr::literal(LiteralValue::NoVal)
+}
+
+pub fn binary(left: Expr, operator: &str, right: Expr) -> Expr {
+    Expr::binary(left, tok(operator), right)
+}
+
+pub fn unary(operator: &str, right: Expr) -> Expr {
+    Expr::unary(tok(operator), right)
+}
+
+pub fn group(expression: Expr) -> Expr {
+    Expr::grouping(expression)
+}
+
+pub fn expression(expression: Expr) -> Stmt {
+    Stmt::expression(expression)
+}
+
+pub fn print(expression: Expr) -> Stmt {
+    Stmt::print(expression)
+}
+
+pub fn block(statements: Vec<Stmt>) -> Stmt {
+    Stmt::block(statements)
+}
+
+pub fn if_then(condition: Expr, then_branch: Stmt) -> Stmt {
+    Stmt::if_stmt(condition, then_branch, None)
+}
+
+pub fn if_else(condition: Expr, then_branch: Stmt, else_branch: Stmt) -> Stmt {
+    Stmt::if_stmt(condition, then_branch, Some(else_branch))
+}
+
+pub fn function(name: &str, params: &[&str], body: Vec<Stmt>) -> Stmt {
+    let params = params.iter().map(|p| tok(p)).collect();
+    Stmt::function(tok(name), params, Rc::new(body))
+}
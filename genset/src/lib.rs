@@ -1,7 +1,11 @@
 use crate::gen::expr::{Accept, Binary, Expr, Grouping, Literal, Unary};
 use std::borrow::Borrow;
 
-mod gen;
+pub mod dsl;
+/// The syntax tree, generated from `grammar.ast` by the build script.
+pub mod gen {
+    include!(concat!(env!("OUT_DIR"), "/ast.rs"));
+}
 
 pub struct AstPrinter;
 
@@ -30,7 +34,7 @@ impl gen::expr::Visitor<String> for AstPrinter {
     fn visit_binary_expr(&self, binary: &Binary) -> String {
         let left = binary.left.borrow();
         let right = binary.right.borrow();
-        self.parenthesize(binary.operator.lexeme.as_str(), &vec![left, right])
+        self.parenthesize(&binary.operator.lexeme, &vec![left, right])
     }
 
     fn visit_grouping_expr(&self, grouping: &Grouping) -> String {
@@ -44,27 +48,32 @@ impl gen::expr::Visitor<String> for AstPrinter {
 
     fn visit_unary_expr(&self, unary: &Unary) -> String {
         let right = unary.right.borrow();
-        self.parenthesize(unary.operator.lexeme.as_str(), &vec![right])
+        self.parenthesize(&unary.operator.lexeme, &vec![right])
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::gen::expr::{Binary, Expr, Grouping, Literal, Unary};
+    use crate::dsl::*;
+    use crate::gen::expr::{AcceptMut, Binary, Expr, Grouping, Literal, Unary, VisitorMut};
+    use crate::gen::stmt;
+    use crate::gen::{Fold, Walker};
     use crate::AstPrinter;
-    use shared::tokens::{LiteralValue, Token, TokenType};
+    use shared::tokens::{LiteralValue, TokenType};
+
+    mod cyclic {
+        include!(concat!(env!("OUT_DIR"), "/cyclic.rs"));
+    }
+
+    fn sample() -> Expr {
+        binary(unary("-", num(123)), "*", group(float(45.67)))
+    }
 
     #[test]
-    fn unary() {
+    fn print_unary() {
         let a = AstPrinter {};
 
-        let u = Expr::Unary(Unary {
-            operator: Token::new(TokenType::PLUS, "+".to_string(), 1, LiteralValue::NoVal),
-            right: Box::new(Expr::Literal(Literal {
-                value: LiteralValue::Num(45),
-            })),
-        });
-        let r = a.print(&u);
+        let r = a.print(&unary("+", num(45)));
 
         assert_eq!(r, "(+ 45)");
     }
@@ -73,23 +82,129 @@ mod tests {
     fn grouping() {
         let a = AstPrinter {};
 
-        let expr = Expr::Binary(Binary {
-            left: Box::new(Expr::Unary(Unary {
-                operator: Token::new(TokenType::MINUS, "-".to_string(), 1, LiteralValue::NoVal),
-                right: Box::new(Expr::Literal(Literal {
-                    value: LiteralValue::Num(123),
-                })),
-            })),
-            operator: Token::new(TokenType::STAR, "*".to_string(), 1, LiteralValue::NoVal),
-            right: Box::new(Expr::Grouping(Grouping {
-                expression: Box::new(Expr::Literal(Literal {
-                    value: LiteralValue::NumFloat(45.67),
-                })),
-            })),
-        });
-
-        let res = a.print(&expr);
+        let res = a.print(&sample());
 
         assert_eq!(res, "(* (- 123) (group 45.67))");
     }
+
+    // collects literals in visiting order
+    struct Literals(Vec<String>);
+
+    impl VisitorMut<()> for Literals {
+        fn visit_binary_expr(&mut self, binary: &Binary) {
+            binary.left.accept_mut(self);
+            binary.right.accept_mut(self);
+        }
+
+        fn visit_grouping_expr(&mut self, grouping: &Grouping) {
+            grouping.expression.accept_mut(self);
+        }
+
+        fn visit_literal_expr(&mut self, literal: &Literal) {
+            self.0.push(literal.value.to_string());
+        }
+
+        fn visit_unary_expr(&mut self, unary: &Unary) {
+            unary.right.accept_mut(self);
+        }
+    }
+
+    #[test]
+    fn visit_mut() {
+        let mut literals = Literals(vec![]);
+        sample().accept_mut(&mut literals);
+
+        assert_eq!(literals.0, vec!["123", "45.67"]);
+    }
+
+    // turns `-` applied to a number literal into a negative literal
+    struct FoldNegation;
+
+    impl Fold for FoldNegation {
+        fn fold_unary_expr(&mut self, unary: Unary) -> Expr {
+            match (&unary.operator.tt, self.fold_expr(*unary.right)) {
+                (
+                    TokenType::MINUS,
+                    Expr::Literal(Literal {
+                        value: LiteralValue::Num(n),
+                    }),
+                ) => Expr::literal(LiteralValue::Num(-n)),
+                (_, right) => Expr::unary(unary.operator, right),
+            }
+        }
+    }
+
+    #[test]
+    fn fold() {
+        let folded = FoldNegation.fold_expr(sample());
+
+        assert_eq!(AstPrinter.print(&folded), "(* -123 (group 45.67))");
+        assert_eq!(folded, binary(num(-123), "*", group(float(45.67))));
+    }
+
+    #[test]
+    fn fold_function_body() {
+        let body = vec![print(unary("-", num(1)))];
+        let shared = function("f", &["a"], body.clone());
+        let kept = shared.clone();
+
+        // the body is still shared with `kept`, it gets cloned rather than changed
+        let folded = FoldNegation.fold_stmt(shared);
+
+        assert_eq!(folded, function("f", &["a"], vec![print(num(-1))]));
+        assert_eq!(kept, function("f", &["a"], body));
+    }
+
+    // only cares about literals, everything else is walked by the defaults
+    #[derive(Default)]
+    struct LiteralCollector(Vec<String>);
+
+    impl Walker for LiteralCollector {
+        fn walk_literal_expr(&mut self, literal: &Literal) {
+            self.0.push(literal.value.to_string());
+        }
+    }
+
+    #[test]
+    fn walk() {
+        let program = block(vec![
+            print(sample()),
+            if_then(string("yes"), expression(num(1))),
+        ]);
+
+        let mut collector = LiteralCollector::default();
+        stmt::walk_stmt(&mut collector, &program);
+
+        assert_eq!(collector.0, vec!["123", "45.67", "yes", "1"]);
+    }
+
+    // `Literals` is also a `VisitorMut`, the hooks must not be ambiguous
+    impl Walker for Literals {
+        fn walk_literal_expr(&mut self, literal: &Literal) {
+            self.visit_literal_expr(literal);
+        }
+    }
+
+    #[test]
+    fn walk_visitor() {
+        let mut literals = Literals(vec![]);
+        literals.walk_expr(&sample());
+
+        assert_eq!(literals.0, vec!["123", "45.67"]);
+    }
+
+    #[test]
+    fn cyclic_bases() {
+        use cyclic::{left::Left, right::Right, Fold};
+
+        struct Keep;
+        impl Fold for Keep {}
+
+        let tree = Left::pair(
+            Right::pair(Left::stop(tok("a"))),
+            Some(Right::stop(tok("b"))),
+        );
+
+        assert_eq!(Keep.fold_left(tree.clone()), tree);
+    }
 }
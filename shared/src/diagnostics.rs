@@ -0,0 +1,368 @@
+use crate::json;
+use crate::source_map::SourceMap;
+use crate::tokens::Span;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A span with an explanation of what is wrong there, the message may be empty.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem to report to the user, found by any stage.
+/// The primary label points at the problem itself, secondary ones at related code.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Option<Label>,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            primary: None,
+            secondary: vec![],
+            notes: vec![],
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, message)
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.primary = Some(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Every label, the primary one first.
+    fn labels(&self) -> impl Iterator<Item = (bool, &Label)> {
+        let primary = self.primary.iter().map(|l| (true, l));
+        primary.chain(self.secondary.iter().map(|l| (false, l)))
+    }
+
+    /// Renders the diagnostic, the label spans have to be in `sources`.
+    pub fn render(&self, sources: &SourceMap, format: Format) -> String {
+        match format {
+            Format::Terminal { color } => render_terminal(self, sources, color),
+            Format::Plain => render_plain(self, sources),
+            Format::Json => render_json(self, sources),
+        }
+    }
+}
+
+/// How diagnostics are rendered.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Format {
+    /// The labelled source lines with underlines, optionally coloured with ANSI escapes.
+    Terminal { color: bool },
+    /// One `file:line:column: ...` line per label, easy to grep in logs.
+    Plain,
+    /// One JSON object.
+    Json,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BLUE: &str = "\x1b[1;34m";
+
+fn severity_style(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Error => "\x1b[1;31m",
+        Severity::Warning => "\x1b[1;33m",
+        Severity::Note => "\x1b[1;32m",
+    }
+}
+
+struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Line, column and the source line a label starts on, with the number of chars to
+/// underline. Spans covering several lines are underlined up to the end of the first.
+fn label_position<'s>(sources: &'s SourceMap, span: &Span) -> (u32, u32, &'s str, usize) {
+    let file = sources.file(span.file);
+    let (line, column) = file.line_column(span.start);
+    let src = file.src();
+    let covered = &src[span.start.min(src.len())..span.end.min(src.len())];
+    let first_line = covered.split(&['\r', '\n'][..]).next().unwrap_or("");
+    let text = file.line(line).unwrap_or("");
+    (line, column, text, first_line.chars().count().max(1))
+}
+
+fn render_terminal(diag: &Diagnostic, sources: &SourceMap, color: bool) -> String {
+    let painter = Painter { color };
+    let style = severity_style(diag.severity);
+    let mut out = format!(
+        "{}{}\n",
+        painter.paint(style, &diag.severity.to_string()),
+        painter.paint(BOLD, &format!(": {}", diag.message))
+    );
+
+    // labels grouped by file, in the order the files first show up
+    let mut files: Vec<(_, Vec<(bool, &Label)>)> = vec![];
+    for (primary, label) in diag.labels() {
+        match files.iter_mut().find(|(f, _)| *f == label.span.file) {
+            Some((_, labels)) => labels.push((primary, label)),
+            None => files.push((label.span.file, vec![(primary, label)])),
+        }
+    }
+
+    let width = diag
+        .labels()
+        .map(|(_, l)| label_position(sources, &l.span).0.to_string().len())
+        .max()
+        .unwrap_or(0);
+    let gutter = " ".repeat(width);
+    let bar = painter.paint(BLUE, "|");
+
+    for (i, (file, labels)) in files.iter().enumerate() {
+        let (line, column, _, _) = label_position(sources, &labels[0].1.span);
+        let arrow = if i == 0 { "-->" } else { ":::" };
+        out += &format!(
+            "{}{} {}:{}:{}\n",
+            gutter,
+            painter.paint(BLUE, arrow),
+            sources.name(*file),
+            line,
+            column
+        );
+        out += &format!("{} {}\n", gutter, bar);
+
+        let mut positions: Vec<_> = labels
+            .iter()
+            .map(|(primary, label)| (label_position(sources, &label.span), *primary, label))
+            .collect();
+        positions.sort_by_key(|((line, column, _, _), _, _)| (*line, *column));
+
+        let mut last_line = None;
+        for ((line, column, text, len), primary, label) in positions {
+            if last_line != Some(line) {
+                let number = format!("{:>width$}", line, width = width);
+                out += &format!("{} {} {}\n", painter.paint(BLUE, &number), bar, text);
+                last_line = Some(line);
+            }
+
+            // keep tabs so the underline lines up with the source line
+            let indent: String = text
+                .chars()
+                .take(column as usize - 1)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let (mark, mark_style) = if primary { ('^', style) } else { ('-', BLUE) };
+            let mut underline = mark.to_string().repeat(len);
+            if !label.message.is_empty() {
+                underline = format!("{} {}", underline, label.message);
+            }
+            out += &format!(
+                "{} {} {}{}\n",
+                gutter,
+                bar,
+                indent,
+                painter.paint(mark_style, &underline)
+            );
+        }
+    }
+
+    for note in &diag.notes {
+        out += &format!("{} {} note: {}\n", gutter, painter.paint(BLUE, "="), note);
+    }
+
+    out
+}
+
+fn render_plain(diag: &Diagnostic, sources: &SourceMap) -> String {
+    let at = |label: &Label| {
+        let (line, column, _, _) = label_position(sources, &label.span);
+        format!("{}:{}:{}: ", sources.name(label.span.file), line, column)
+    };
+
+    let mut out = match &diag.primary {
+        Some(label) => at(label),
+        None => String::new(),
+    };
+    out += &format!("{}: {}\n", diag.severity, diag.message);
+
+    for (_, label) in diag.labels().filter(|(_, l)| !l.message.is_empty()) {
+        out += &format!("{}note: {}\n", at(label), label.message);
+    }
+    for note in &diag.notes {
+        out += &format!("note: {}\n", note);
+    }
+
+    out
+}
+
+fn render_json(diag: &Diagnostic, sources: &SourceMap) -> String {
+    let labels: Vec<String> = diag
+        .labels()
+        .map(|(primary, label)| {
+            let span = &label.span;
+            let (line, column, _, _) = label_position(sources, span);
+            format!(
+                "{{\"primary\":{},\"file\":{},\"start\":{},\"end\":{},\"line\":{},\"column\":{},\"message\":{}}}",
+                primary,
+                json::quote(sources.name(span.file)),
+                span.start,
+                span.end,
+                line,
+                column,
+                json::quote(&label.message)
+            )
+        })
+        .collect();
+    let notes: Vec<String> = diag.notes.iter().map(|n| json::quote(n)).collect();
+
+    format!(
+        "{{\"severity\":\"{}\",\"message\":{},\"labels\":[{}],\"notes\":[{}]}}\n",
+        diag.severity,
+        json::quote(&diag.message),
+        labels.join(","),
+        notes.join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source_map::FileId;
+
+    fn sources() -> (SourceMap, FileId, FileId) {
+        let mut sources = SourceMap::new();
+        let main = sources.add("main.lox", "var a = 1;\n\tprint a @ b;\n");
+        let lib = sources.add("lib.lox", "fun f() {}");
+        (sources, main, lib)
+    }
+
+    fn diagnostic(main: FileId, lib: FileId) -> Diagnostic {
+        Diagnostic::error("Unexpected character '@'")
+            .with_primary(Span::new(20, 21, 2, 10).in_file(main), "not an operator")
+            .with_secondary(Span::new(4, 5, 1, 5).in_file(main), "declared here")
+            .with_secondary(Span::new(4, 5, 1, 5).in_file(lib), "")
+            .with_note("operators are + - * /")
+    }
+
+    #[test]
+    fn render_terminal() {
+        let (sources, main, lib) = sources();
+        let out = diagnostic(main, lib).render(&sources, Format::Terminal { color: false });
+
+        let expected = "\
+error: Unexpected character '@'
+ --> main.lox:2:10
+  |
+1 | var a = 1;
+  |     - declared here
+2 | \tprint a @ b;
+  | \t        ^ not an operator
+ ::: lib.lox:1:5
+  |
+1 | fun f() {}
+  |     -
+  = note: operators are + - * /
+";
+        assert_eq!(out, expected);
+
+        let colored = diagnostic(main, lib).render(&sources, Format::Terminal { color: true });
+        assert!(colored.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(colored.contains("\x1b[1;31m^ not an operator\x1b[0m"));
+    }
+
+    #[test]
+    fn render_underlines_first_line_of_span() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("s.lox", "print \"open\nstring");
+        let out = Diagnostic::error("Unterminated string")
+            .with_primary(Span::new(6, 18, 1, 7).in_file(file), "")
+            .render(&sources, Format::Terminal { color: false });
+
+        assert!(out.ends_with("1 | print \"open\n  |       ^^^^^\n"));
+    }
+
+    #[test]
+    fn render_plain() {
+        let (sources, main, lib) = sources();
+        let out = diagnostic(main, lib).render(&sources, Format::Plain);
+
+        let expected = "\
+main.lox:2:10: error: Unexpected character '@'
+main.lox:2:10: note: not an operator
+main.lox:1:5: note: declared here
+note: operators are + - * /
+";
+        assert_eq!(out, expected);
+        assert_eq!(
+            Diagnostic::warning("w").render(&sources, Format::Plain),
+            "warning: w\n"
+        );
+    }
+
+    #[test]
+    fn render_json() {
+        let (sources, main, lib) = sources();
+        let out = diagnostic(main, lib).render(&sources, Format::Json);
+
+        let expected = concat!(
+            r#"{"severity":"error","message":"Unexpected character '@'","labels":["#,
+            r#"{"primary":true,"file":"main.lox","start":20,"end":21,"line":2,"column":10,"message":"not an operator"},"#,
+            r#"{"primary":false,"file":"main.lox","start":4,"end":5,"line":1,"column":5,"message":"declared here"},"#,
+            r#"{"primary":false,"file":"lib.lox","start":4,"end":5,"line":1,"column":5,"message":""}"#,
+            r#"],"notes":["operators are + - * /"]}"#,
+            "\n"
+        );
+        assert_eq!(out, expected);
+    }
+}
@@ -1 +1,5 @@
+pub mod diagnostics;
+pub mod json;
+pub mod source_map;
+pub mod symbols;
 pub mod tokens;
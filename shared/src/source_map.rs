@@ -0,0 +1,228 @@
+use crate::symbols::Interner;
+use std::cell::RefCell;
+
+/// Index of a source text in a `SourceMap`.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileId(u32);
+
+impl FileId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Where a byte offset falls in a file, `line` and `column` are 1-based and columns are
+/// counted in chars like the scanner does, a leading byte order mark takes none.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct Location {
+    pub file: FileId,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A loaded source text with the offsets its lines start at.
+#[derive(Debug)]
+pub struct SourceFile {
+    id: FileId,
+    name: String,
+    src: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    fn new(id: FileId, name: String, src: String) -> Self {
+        let line_starts = line_starts(&src);
+        SourceFile {
+            id,
+            name,
+            src,
+            line_starts,
+        }
+    }
+
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    /// Line and column of the byte `offset`, offsets past the end are clamped to it.
+    pub fn line_column(&self, offset: usize) -> (u32, u32) {
+        let offset = offset.min(self.src.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(i) => i,
+            Err(i) => i - 1,
+        };
+        let start = self.line_start(line);
+        let column = self.src[start..offset.max(start)].chars().count() + 1;
+        (line as u32 + 1, column as u32)
+    }
+
+    // offset of the first visible char of the 0-based `line`
+    fn line_start(&self, line: usize) -> usize {
+        let start = self.line_starts[line];
+        if line == 0 && self.src.starts_with(BOM) {
+            start + BOM.len_utf8()
+        } else {
+            start
+        }
+    }
+
+    /// Text of the 1-based `line` without its line break or a leading byte order mark.
+    pub fn line(&self, line: u32) -> Option<&str> {
+        let i = (line as usize).checked_sub(1)?;
+        if i >= self.line_starts.len() {
+            return None;
+        }
+        let start = self.line_start(i);
+        let end = self
+            .line_starts
+            .get(i + 1)
+            .copied()
+            .unwrap_or(self.src.len());
+        Some(self.src[start..end].trim_end_matches(&['\r', '\n'][..]))
+    }
+}
+
+const BOM: char = '\u{FEFF}';
+
+// offsets just past every line break, `\r\n`, `\r` and `\n` each end a line
+fn break_ends(s: &str) -> impl Iterator<Item = usize> + '_ {
+    let bytes = s.as_bytes();
+    bytes.iter().enumerate().filter_map(move |(i, b)| match b {
+        b'\r' if bytes.get(i + 1) == Some(&b'\n') => None,
+        b'\r' | b'\n' => Some(i + 1),
+        _ => None,
+    })
+}
+
+fn line_starts(src: &str) -> Vec<usize> {
+    std::iter::once(0).chain(break_ends(src)).collect()
+}
+
+/// Counts the line breaks in `s`, where `\r\n`, `\n` and a lone `\r` all count as one,
+/// and returns them along with the text after the last one.
+pub fn line_breaks(s: &str) -> (u32, &str) {
+    let (breaks, line_start) = break_ends(s).fold((0, 0), |(n, _), end| (n + 1, end));
+    (breaks, &s[line_start..])
+}
+
+/// Owns every source text loaded so far. Spans only store a `FileId`, the map turns
+/// them back into file names and source lines.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+    symbols: RefCell<Interner>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, src: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len() as u32);
+        self.files
+            .push(SourceFile::new(id, name.into(), src.into()));
+        id
+    }
+
+    /// Panics for ids that don't come from this map.
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.index()]
+    }
+
+    pub fn src(&self, id: FileId) -> &str {
+        self.file(id).src()
+    }
+
+    pub fn name(&self, id: FileId) -> &str {
+        self.file(id).name()
+    }
+
+    pub fn location(&self, id: FileId, offset: usize) -> Location {
+        let (line, column) = self.file(id).line_column(offset);
+        Location {
+            file: id,
+            line,
+            column,
+        }
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    /// Names of the identifiers scanned from these sources. Scanners only borrow the map,
+    /// so they intern through the cell.
+    pub fn symbols(&self) -> &RefCell<Interner> {
+        &self.symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations() {
+        let mut sources = SourceMap::new();
+        let a = sources.add("a.lox", "print 1;\r\nvar é = 2;\rx\n");
+        let b = sources.add("b.lox", "one\ntwo");
+
+        assert_ne!(a, b);
+        assert_eq!(sources.name(b), "b.lox");
+        assert_eq!(sources.src(b), "one\ntwo");
+
+        let at = |id, offset| {
+            let loc = sources.location(id, offset);
+            (loc.line, loc.column)
+        };
+        assert_eq!(at(a, 0), (1, 1));
+        assert_eq!(at(a, 8), (1, 9));
+        assert_eq!(at(a, 10), (2, 1));
+        assert_eq!(at(a, 16), (2, 6));
+        assert_eq!(at(a, 22), (3, 1));
+        assert_eq!(at(a, 24), (4, 1));
+        assert_eq!(at(b, 5), (2, 2));
+        assert_eq!(at(b, 100), (2, 4));
+
+        let file = sources.file(a);
+        assert_eq!(file.line_count(), 4);
+        assert_eq!(file.line(1), Some("print 1;"));
+        assert_eq!(file.line(2), Some("var é = 2;"));
+        assert_eq!(file.line(3), Some("x"));
+        assert_eq!(file.line(4), Some(""));
+        assert_eq!(file.line(0), None);
+        assert_eq!(file.line(5), None);
+    }
+
+    #[test]
+    fn byte_order_mark_takes_no_column() {
+        let mut sources = SourceMap::new();
+        let file = sources.add("p.lox", "\u{FEFF}var a = @;\nb");
+
+        assert_eq!(sources.file(file).line_column(0), (1, 1));
+        assert_eq!(sources.file(file).line_column(11), (1, 9));
+        assert_eq!(sources.file(file).line_column(14), (2, 1));
+        assert_eq!(sources.file(file).line(1), Some("var a = @;"));
+    }
+
+    #[test]
+    fn count_line_breaks() {
+        assert_eq!(line_breaks("no breaks"), (0, "no breaks"));
+        assert_eq!(line_breaks("a\r\nb\rc\nd"), (3, "d"));
+        assert_eq!(line_breaks("a\n\r"), (2, ""));
+    }
+}
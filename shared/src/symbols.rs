@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+/// Cheap handle to a name interned in an `Interner`, comparing and hashing one is O(1).
+/// A symbol only means something to the interner that made it.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+/// Table of interned names. It is owned by whoever scans, usually next to the sources in
+/// a `SourceMap`, and its names are freed along with it.
+#[derive(Debug, Default)]
+pub struct Interner {
+    ids: HashMap<String, Symbol>,
+    names: Vec<String>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Interner::default()
+    }
+
+    /// Returns the symbol for `name`, the same name always gets the same symbol.
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(sym) = self.ids.get(name) {
+            return *sym;
+        }
+
+        let sym = Symbol(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), sym);
+        sym
+    }
+
+    /// Panics for symbols that don't come from this interner.
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        &self.names[sym.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_and_resolve() {
+        let mut interner = Interner::new();
+        let a = interner.intern("interned_a");
+        let b = interner.intern("interned_b");
+
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("interned_a"), a);
+        assert_eq!(interner.intern(&String::from("interned_b")), b);
+        assert_eq!(interner.resolve(a), "interned_a");
+        assert_eq!(interner.resolve(b), "interned_b");
+        assert_eq!(interner.len(), 2);
+    }
+}
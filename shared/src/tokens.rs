@@ -1,7 +1,11 @@
+use crate::source_map::FileId;
+use crate::symbols::Symbol;
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter};
 
 #[allow(non_camel_case_types)]
 #[derive(Debug, Eq, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TokenType {
     // Single-character tokens.
     LEFT_PAREN,
@@ -29,6 +33,8 @@ pub enum TokenType {
     // Literals.
     IDENTIFIER,
     STRING,
+    // string part followed by an interpolated `${ expression }`
+    INTERPOLATION,
     NUMBER,
 
     // Keywords.
@@ -49,41 +55,276 @@ pub enum TokenType {
     VAR,
     WHILE,
 
+    // Dialect extensions, only scanned when enabled in the scanner configuration.
+    PERCENT,
+    QUESTION,
+    COLON,
+    QUESTION_COLON,
+    FAT_ARROW,
+    BREAK,
+    CONTINUE,
+
     EOF,
 }
 
-pub enum LiteralValue {
+/// Every reserved word with the token it scans to.
+pub const KEYWORDS: [(&str, TokenType); 16] = [
+    ("and", TokenType::AND),
+    ("class", TokenType::CLASS),
+    ("else", TokenType::ELSE),
+    ("false", TokenType::FALSE),
+    ("for", TokenType::FOR),
+    ("fun", TokenType::FUN),
+    ("if", TokenType::IF),
+    ("nil", TokenType::NIL),
+    ("or", TokenType::OR),
+    ("print", TokenType::PRINT),
+    ("return", TokenType::RETURN),
+    ("super", TokenType::SUPER),
+    ("this", TokenType::THIS),
+    ("true", TokenType::TRUE),
+    ("var", TokenType::VAR),
+    ("while", TokenType::WHILE),
+];
+
+/// Reserved words outside of jlox, a dialect has to opt into them.
+pub const EXTENSION_KEYWORDS: [(&str, TokenType); 2] = [
+    ("break", TokenType::BREAK),
+    ("continue", TokenType::CONTINUE),
+];
+
+/// Looks up the keyword spelled by `ident`.
+/// Dispatches on the length and first byte so most identifiers are rejected without
+/// comparing any string.
+pub fn keyword(ident: &str) -> Option<TokenType> {
+    use TokenType::*;
+
+    let bytes = ident.as_bytes();
+    let (tt, txt) = match (bytes.len(), *bytes.first()?) {
+        (2, b'i') => (IF, "if"),
+        (2, b'o') => (OR, "or"),
+        (3, b'a') => (AND, "and"),
+        (3, b'f') => match bytes[1] {
+            b'o' => (FOR, "for"),
+            _ => (FUN, "fun"),
+        },
+        (3, b'n') => (NIL, "nil"),
+        (3, b'v') => (VAR, "var"),
+        (4, b'e') => (ELSE, "else"),
+        (4, b't') => match bytes[1] {
+            b'h' => (THIS, "this"),
+            _ => (TRUE, "true"),
+        },
+        (5, b'c') => (CLASS, "class"),
+        (5, b'f') => (FALSE, "false"),
+        (5, b'p') => (PRINT, "print"),
+        (5, b's') => (SUPER, "super"),
+        (5, b'w') => (WHILE, "while"),
+        (6, b'r') => (RETURN, "return"),
+        _ => return None,
+    };
+
+    if ident == txt {
+        Some(tt)
+    } else {
+        None
+    }
+}
+
+impl TokenType {
+    /// The reserved word for keyword tokens.
+    pub fn keyword_text(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .chain(EXTENSION_KEYWORDS.iter())
+            .find(|(_, tt)| tt == self)
+            .map(|(txt, _)| *txt)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LiteralValue<'src> {
     NoVal,
     Num(i64),
     NumFloat(f64),
-    Str(String),
+    Str(Cow<'src, str>),
+}
+
+impl<'src> LiteralValue<'src> {
+    pub fn into_owned(self) -> LiteralValue<'static> {
+        match self {
+            LiteralValue::NoVal => LiteralValue::NoVal,
+            LiteralValue::Num(n) => LiteralValue::Num(n),
+            LiteralValue::NumFloat(f) => LiteralValue::NumFloat(f),
+            LiteralValue::Str(s) => LiteralValue::Str(Cow::Owned(s.into_owned())),
+        }
+    }
+}
+
+/// Location of a lexeme in the source.
+/// `start` and `end` are byte offsets into `file` (end exclusive), `line` and `column`
+/// are 1-based and point at the first character of the lexeme.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub file: FileId,
+}
+
+impl Span {
+    /// Span in the default file, use `in_file` to place it elsewhere.
+    pub fn new(start: usize, end: usize, line: u32, column: u32) -> Self {
+        Span {
+            start,
+            end,
+            line,
+            column,
+            file: FileId::default(),
+        }
+    }
+
+    pub fn in_file(self, file: FileId) -> Self {
+        Span { file, ..self }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+    ByteOrderMark,
+    Shebang,
+    /// Text that failed to scan, it was reported as an error instead of becoming a token.
+    Skipped,
+}
+
+/// Source text that doesn't produce tokens but is kept around so the original source
+/// can be reconstructed.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trivia<'src> {
+    pub kind: TriviaKind,
+    pub text: Cow<'src, str>,
+    pub span: Span,
+}
+
+impl<'src> Trivia<'src> {
+    pub fn into_owned(self) -> Trivia<'static> {
+        Trivia {
+            kind: self.kind,
+            text: Cow::Owned(self.text.into_owned()),
+            span: self.span,
+        }
+    }
 }
 
-pub struct Token {
+/// A token borrowing its lexeme (and string literal when possible) from the scanned source.
+/// Use `into_owned` to get a `Token<'static>` that outlives the source.
+///
+/// Identifiers carry the `symbol` their name is interned as in the `SourceMap` they were
+/// scanned from.
+///
+/// The trivia lists are only filled when the scanner is asked to keep trivia. Trailing
+/// trivia runs up to the end of the token's line, anything after that is leading trivia
+/// of the next token.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token<'src> {
     pub tt: TokenType,
-    pub lexeme: String,
-    line: u32,
-    pub literal: LiteralValue,
+    pub lexeme: Cow<'src, str>,
+    span: Span,
+    pub literal: LiteralValue<'src>,
+    pub symbol: Option<Symbol>,
+    pub leading_trivia: Vec<Trivia<'src>>,
+    pub trailing_trivia: Vec<Trivia<'src>>,
 }
 
-impl Token {
-    pub fn new(tt: TokenType, lexeme: String, line: u32, literal: LiteralValue) -> Self {
+impl<'src> Token<'src> {
+    /// Creates a token that only knows its line, handy for tokens that don't come from
+    /// a scanned source.
+    pub fn new(
+        tt: TokenType,
+        lexeme: impl Into<Cow<'src, str>>,
+        line: u32,
+        literal: LiteralValue<'src>,
+    ) -> Self {
+        let span = Span {
+            line,
+            ..Span::default()
+        };
+        Token::with_span(tt, lexeme, span, literal)
+    }
+
+    pub fn with_span(
+        tt: TokenType,
+        lexeme: impl Into<Cow<'src, str>>,
+        span: Span,
+        literal: LiteralValue<'src>,
+    ) -> Self {
         Token {
             tt,
-            lexeme,
-            line,
+            lexeme: lexeme.into(),
+            span,
             literal,
+            symbol: None,
+            leading_trivia: vec![],
+            trailing_trivia: vec![],
+        }
+    }
+
+    /// Copies whatever is still borrowed from the source.
+    pub fn into_owned(self) -> Token<'static> {
+        Token {
+            tt: self.tt,
+            lexeme: Cow::Owned(self.lexeme.into_owned()),
+            span: self.span,
+            literal: self.literal.into_owned(),
+            symbol: self.symbol,
+            leading_trivia: owned_trivia(self.leading_trivia),
+            trailing_trivia: owned_trivia(self.trailing_trivia),
         }
     }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
+    pub fn line(&self) -> u32 {
+        self.span.line
+    }
+}
+
+fn owned_trivia(trivia: Vec<Trivia<'_>>) -> Vec<Trivia<'static>> {
+    trivia.into_iter().map(Trivia::into_owned).collect()
 }
 
-impl Display for Token {
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?} {} ", self.tt, self.lexeme)
     }
 }
 
-impl Display for LiteralValue {
+impl Display for LiteralValue<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             LiteralValue::NoVal => write!(f, "nil"),
@@ -93,3 +334,56 @@ impl Display for LiteralValue {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keyword_lookup() {
+        for (txt, tt) in KEYWORDS.iter() {
+            assert_eq!(keyword(txt).as_ref(), Some(tt));
+            assert_eq!(tt.keyword_text(), Some(*txt));
+        }
+
+        for ident in [
+            "", "i", "fo", "fox", "thus", "While", "classes", "ret", "éé",
+        ]
+        .iter()
+        {
+            assert_eq!(keyword(ident), None);
+        }
+        assert_eq!(TokenType::IDENTIFIER.keyword_text(), None);
+        assert_eq!(keyword("break"), None);
+        assert_eq!(TokenType::BREAK.keyword_text(), Some("break"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let mut tok = Token::with_span(
+            TokenType::STRING,
+            "\"hi\"",
+            Span::new(4, 8, 1, 5),
+            LiteralValue::Str(Cow::Borrowed("hi")),
+        );
+        tok.leading_trivia.push(Trivia {
+            kind: TriviaKind::Whitespace,
+            text: Cow::Borrowed(" "),
+            span: Span::new(3, 4, 1, 4),
+        });
+        let toks = vec![
+            tok,
+            Token::new(TokenType::NUMBER, "1.5", 1, LiteralValue::NumFloat(1.5)),
+            Token::new(TokenType::EOF, "", 1, LiteralValue::NoVal),
+        ];
+
+        let json = serde_json::to_string(&toks).unwrap();
+        let back: Vec<Token> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, toks);
+
+        let span: Span =
+            serde_json::from_str(r#"{"start":4,"end":8,"line":1,"column":5}"#).unwrap();
+        assert_eq!(back[0].span(), &span);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::scanner::{ScanError, Scanner, ScannerConfig};
+use shared::json;
+use shared::source_map::{FileId, SourceMap};
+use shared::tokens::{LiteralValue, Token};
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+
+/// Output formats for `--dump-tokens`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DumpFormat {
+    /// One JSON object per line.
+    Json,
+    /// Human readable table.
+    Text,
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(DumpFormat::Json),
+            "text" => Ok(DumpFormat::Text),
+            _ => Err(format!("Unknown dump format '{}'", s)),
+        }
+    }
+}
+
+/// Writes every token scanned from `file` to `out` as they are produced.
+/// Scan errors don't stop the dump, they are returned once the whole file was scanned.
+pub fn dump_tokens<W: Write>(
+    sources: &SourceMap,
+    file: FileId,
+    format: DumpFormat,
+    out: &mut W,
+) -> io::Result<Vec<ScanError>> {
+    let mut errors = vec![];
+
+    if format == DumpFormat::Text {
+        writeln!(
+            out,
+            "{:<9} {:<14} {:<20} LITERAL",
+            "LINE:COL", "TYPE", "LEXEME"
+        )?;
+    }
+
+    for res in Scanner::new(sources, file, ScannerConfig::default()) {
+        match res {
+            Ok(tok) => match format {
+                DumpFormat::Json => writeln!(out, "{}", to_json(&tok))?,
+                DumpFormat::Text => writeln!(out, "{}", to_row(&tok))?,
+            },
+            Err(e) => errors.push(e),
+        }
+    }
+
+    Ok(errors)
+}
+
+fn to_json(tok: &Token) -> String {
+    let span = tok.span();
+    format!(
+        "{{\"type\":\"{:?}\",\"lexeme\":{},\"literal\":{},\"span\":{{\"start\":{},\"end\":{},\"line\":{},\"column\":{}}}}}",
+        tok.tt,
+        json::quote(&tok.lexeme),
+        json_literal(&tok.literal),
+        span.start,
+        span.end,
+        span.line,
+        span.column
+    )
+}
+
+fn json_literal(literal: &LiteralValue) -> String {
+    match literal {
+        LiteralValue::NoVal => "null".to_string(),
+        LiteralValue::Num(n) => n.to_string(),
+        // Debug always prints a fraction or exponent so floats stay floats
+        LiteralValue::NumFloat(f) => format!("{:?}", f),
+        LiteralValue::Str(s) => json::quote(s),
+    }
+}
+
+fn to_row(tok: &Token) -> String {
+    let span = tok.span();
+    let literal = match &tok.literal {
+        LiteralValue::NoVal => String::new(),
+        LiteralValue::Str(s) => format!("{:?}", s),
+        l => l.to_string(),
+    };
+    format!(
+        "{:<9} {:<14} {:<20} {}",
+        format!("{}:{}", span.line, span.column),
+        format!("{:?}", tok.tt),
+        format!("{:?}", tok.lexeme),
+        literal
+    )
+    .trim_end()
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dump(program: &str, format: DumpFormat) -> (String, Vec<ScanError>) {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", program);
+        let mut out = vec![];
+        let errors = dump_tokens(&sources, file, format, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), errors)
+    }
+
+    #[test]
+    fn dump_json() {
+        let (out, errors) = dump("var s = \"a\\\"b\";\n1.0", DumpFormat::Json);
+
+        assert!(errors.is_empty());
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                r#"{"type":"VAR","lexeme":"var","literal":null,"span":{"start":0,"end":3,"line":1,"column":1}}"#,
+                r#"{"type":"IDENTIFIER","lexeme":"s","literal":null,"span":{"start":4,"end":5,"line":1,"column":5}}"#,
+                r#"{"type":"EQUAL","lexeme":"=","literal":null,"span":{"start":6,"end":7,"line":1,"column":7}}"#,
+                r#"{"type":"STRING","lexeme":"\"a\\\"b\"","literal":"a\"b","span":{"start":8,"end":14,"line":1,"column":9}}"#,
+                r#"{"type":"SEMICOLON","lexeme":";","literal":null,"span":{"start":14,"end":15,"line":1,"column":15}}"#,
+                r#"{"type":"NUMBER","lexeme":"1.0","literal":1.0,"span":{"start":16,"end":19,"line":2,"column":1}}"#,
+                r#"{"type":"EOF","lexeme":"","literal":null,"span":{"start":19,"end":19,"line":2,"column":4}}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn dump_text() {
+        let (out, errors) = dump("print 42; @", DumpFormat::Text);
+
+        assert_eq!(errors.len(), 1);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                "LINE:COL  TYPE           LEXEME               LITERAL",
+                "1:1       PRINT          \"print\"",
+                "1:7       NUMBER         \"42\"                 42",
+                "1:9       SEMICOLON      \";\"",
+                "1:12      EOF            \"\"",
+            ]
+        );
+    }
+}
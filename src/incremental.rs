@@ -0,0 +1,384 @@
+use crate::scanner::{ScanError, Scanner, ScannerConfig};
+use shared::source_map::line_breaks;
+use shared::symbols::Interner;
+use shared::tokens::{Span, Token};
+use std::cell::RefCell;
+use std::ops::Range;
+
+// How many chars past the end of a lexeme the scanner may look at to decide where it ends
+// (`1.5e-3` needs three). Tokens closer than this to an edit have to be re-scanned.
+const LOOKAHEAD: usize = 3;
+
+/// Replacement of the bytes in `range` of the old source with `text`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Edit<'e> {
+    pub range: Range<usize>,
+    pub text: &'e str,
+}
+
+impl<'e> Edit<'e> {
+    pub fn new(range: Range<usize>, text: &'e str) -> Self {
+        Edit { range, text }
+    }
+
+    pub fn apply(&self, src: &str) -> String {
+        let mut out = String::with_capacity(src.len() - self.range.len() + self.text.len());
+        out.push_str(&src[..self.range.start]);
+        out.push_str(self.text);
+        out.push_str(&src[self.range.end..]);
+        out
+    }
+
+    // signed change in length the edit causes
+    fn delta(&self) -> isize {
+        self.text.len() as isize - self.range.len() as isize
+    }
+}
+
+/// Everything a scan produced, errors don't stop it so both are kept side by side. An
+/// editor keeps this around to relex from on the next edit.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Lexed {
+    pub tokens: Vec<Token<'static>>,
+    pub errors: Vec<ScanError>,
+    // for every token whether the scanner had no interpolation open when it started on
+    // it, plus the state at the end. Scanning can only restart or line up at these points.
+    top_level: Vec<bool>,
+}
+
+impl Lexed {
+    pub fn scan(mut scanner: Scanner) -> Self {
+        let mut lexed = Lexed::default();
+        loop {
+            let at_top_level = scanner.at_top_level();
+            match scanner.next() {
+                Some(Ok(tok)) => {
+                    lexed.tokens.push(tok.into_owned());
+                    lexed.top_level.push(at_top_level);
+                }
+                Some(Err(e)) => lexed.errors.push(e),
+                None => {
+                    lexed.top_level.push(at_top_level);
+                    return lexed;
+                }
+            }
+        }
+    }
+}
+
+/// Updates `lexed`, scanned with `config` from the source before `edit`, to match `src`,
+/// the source after the edit.
+///
+/// Tokens that end well before the edit are kept as they are. Scanning restarts after
+/// the last of them that isn't inside an interpolated string, which also takes care of
+/// edits inside strings and comments since neither has tokens of its own. Once the new
+/// tokens line up again with old ones past the edit the rest of the old tokens are
+/// reused with their spans shifted.
+///
+/// Errors go the same way: old ones before the restart are kept, the re-scanned part
+/// reports its own and old ones in the reused part are shifted along with the tokens.
+///
+/// Re-scanned tokens are placed in the same file as the old ones and their identifiers
+/// are interned in `symbols`, which should be where the old ones were. Trivia is not kept
+/// on them.
+pub fn relex(
+    lexed: &mut Lexed,
+    src: &str,
+    edit: &Edit,
+    symbols: &RefCell<Interner>,
+    config: &ScannerConfig,
+) {
+    let Lexed {
+        mut tokens,
+        errors: old_errors,
+        top_level: mut old_top_level,
+    } = std::mem::take(lexed);
+    // tokens added or removed by hand leave no safe point to restart from
+    if old_top_level.len() != tokens.len() + 1 {
+        old_top_level = vec![false; tokens.len() + 1];
+    }
+    let file = tokens.last().map(|t| t.span().file).unwrap_or_default();
+
+    // tokens ending at least `LOOKAHEAD` chars before the edit can be kept, the last one
+    // after which no interpolation is open is where scanning restarts
+    let limit = src[..edit.range.start]
+        .char_indices()
+        .rev()
+        .nth(LOOKAHEAD - 1)
+        .map(|(i, _)| i);
+    let keepable = match limit {
+        Some(limit) => tokens.partition_point(|t| t.span().end <= limit),
+        None => 0,
+    };
+    let keep = (1..=keepable)
+        .rev()
+        .find(|&k| old_top_level[k])
+        .unwrap_or(0);
+
+    let (offset, line, column) = match keep {
+        0 => (0, 1, 1),
+        _ => end_position(&tokens[keep - 1]),
+    };
+
+    let mut suffix = tokens.split_off(keep);
+    let suffix_top_level = old_top_level.split_off(keep);
+    let edit_end = edit.range.start + edit.text.len();
+    let (mut errors, mut old_errors): (Vec<_>, Vec<_>) =
+        old_errors.into_iter().partition(|e| e.span.start < offset);
+    let mut reused_errors = vec![];
+    let mut top_level = old_top_level;
+    let mut scanner = Scanner::resume(src, file, symbols, config.clone(), offset, line, column);
+
+    loop {
+        let at_top_level = scanner.at_top_level();
+        let tok = match scanner.next() {
+            Some(Ok(tok)) => tok,
+            Some(Err(e)) => {
+                errors.push(e);
+                continue;
+            }
+            None => {
+                top_level.push(at_top_level);
+                break;
+            }
+        };
+
+        let start = tok.span().start;
+        if at_top_level && start >= edit_end {
+            let old_start = (start as isize - edit.delta()) as usize;
+            if let Ok(j) = suffix.binary_search_by_key(&old_start, |t| t.span().start) {
+                // same state at the same text, everything from here on scans the same
+                if suffix_top_level[j] {
+                    top_level.extend_from_slice(&suffix_top_level[j..]);
+                    let rest = suffix.split_off(j);
+                    let shift = Shift::new(edit.delta(), rest[0].span(), tok.span());
+                    tokens.extend(rest.into_iter().map(|mut t| {
+                        t.set_span(shift.span(t.span()));
+                        t
+                    }));
+                    old_errors.retain(|e| e.span.start >= old_start);
+                    reused_errors = old_errors
+                        .into_iter()
+                        .map(|mut e| {
+                            e.span = shift.span(&e.span);
+                            e
+                        })
+                        .collect();
+                    break;
+                }
+            }
+        }
+
+        tokens.push(tok.into_owned());
+        top_level.push(at_top_level);
+    }
+
+    errors.extend(reused_errors);
+
+    *lexed = Lexed {
+        tokens,
+        errors,
+        top_level,
+    };
+}
+
+/// Byte offset, line and column right after `tok`.
+fn end_position(tok: &Token) -> (usize, u32, u32) {
+    let span = tok.span();
+    match line_breaks(&tok.lexeme) {
+        (0, _) => {
+            let column = span.column + tok.lexeme.chars().count() as u32;
+            (span.end, span.line, column)
+        }
+        (breaks, last_line) => {
+            let column = last_line.chars().count() as u32 + 1;
+            (span.end, span.line + breaks, column)
+        }
+    }
+}
+
+/// Moves old spans to where they are after the edit. `before` is where the first reused
+/// token was and `now` where the new scan just found it.
+struct Shift {
+    delta: isize,
+    line: u32,
+    line_delta: i64,
+    column_delta: i64,
+}
+
+impl Shift {
+    fn new(delta: isize, before: &Span, now: &Span) -> Self {
+        Shift {
+            delta,
+            line: before.line,
+            line_delta: now.line as i64 - before.line as i64,
+            column_delta: now.column as i64 - before.column as i64,
+        }
+    }
+
+    fn span(&self, span: &Span) -> Span {
+        // only the rest of the line the edit ended on moves sideways
+        let column = if span.line == self.line {
+            (span.column as i64 + self.column_delta) as u32
+        } else {
+            span.column
+        };
+        Span::new(
+            (span.start as isize + self.delta) as usize,
+            (span.end as isize + self.delta) as usize,
+            (span.line as i64 + self.line_delta) as u32,
+            column,
+        )
+        .in_file(span.file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::source_map::SourceMap;
+    use shared::tokens::LiteralValue;
+
+    fn scan(src: &str) -> Lexed {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", src);
+        Lexed::scan(Scanner::new(&sources, file, ScannerConfig::default()))
+    }
+
+    fn apply_relex(mut old: Lexed, src: &str, edit: &Edit) -> Lexed {
+        relex(
+            &mut old,
+            src,
+            edit,
+            &RefCell::default(),
+            &ScannerConfig::default(),
+        );
+        old
+    }
+
+    fn summary(tokens: &[Token]) -> Vec<String> {
+        tokens
+            .iter()
+            .map(|t| format!("{:?} {:?} {:?} {}", t.tt, t.lexeme, t.span(), t.literal))
+            .collect()
+    }
+
+    fn check(src: &str, edit: Edit) {
+        let new_src = edit.apply(src);
+        let relexed = apply_relex(scan(src), &new_src, &edit);
+        let full = scan(&new_src);
+
+        assert_eq!(
+            summary(&relexed.tokens),
+            summary(&full.tokens),
+            "{:?} after {:?}",
+            new_src,
+            edit
+        );
+        assert_eq!(
+            relexed.errors, full.errors,
+            "{:?} after {:?}",
+            new_src, edit
+        );
+        assert_eq!(
+            relexed.top_level, full.top_level,
+            "{:?} after {:?}",
+            new_src, edit
+        );
+    }
+
+    const PROGRAM: &str = "var a = 1.5;\n/* block\n comment */ fun f(x) {\n  print \"hi ${x + {a}} there\";\n  return x_1 >= 0xFF; // done\n}\nf(\"multi\r\nline\");\r\n";
+
+    #[test]
+    fn relex_matches_full_scan() {
+        let inserts = [
+            "", "a", "1", ".", "\"", "/*", "*/", "}", "${", "\n", "\r", "#!", " ", "é",
+        ];
+
+        for start in 0..=PROGRAM.len() {
+            if !PROGRAM.is_char_boundary(start) {
+                continue;
+            }
+            for len in 0..3 {
+                let end = start + len;
+                if end > PROGRAM.len() || !PROGRAM.is_char_boundary(end) {
+                    continue;
+                }
+                for text in inserts.iter() {
+                    check(PROGRAM, Edit::new(start..end, text));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn relex_reuses_tokens_after_edit() {
+        let src = "var a = 1;\nvar b = 2;\nvar c = 3;";
+        let edit = Edit::new(15..16, "bee\n");
+        let new_src = edit.apply(src);
+
+        let mut old = scan(src);
+        // tag the old tokens to tell reused ones from re-scanned ones
+        old.tokens
+            .iter_mut()
+            .for_each(|t| t.literal = LiteralValue::Str("old".into()));
+
+        let relexed = apply_relex(old, &new_src, &edit).tokens;
+        let reused: Vec<bool> = relexed
+            .iter()
+            .map(|t| t.literal.to_string() == "old")
+            .collect();
+        assert_eq!(reused.iter().filter(|r| **r).count(), 14);
+        assert_eq!(
+            reused[..8],
+            [true, true, true, true, true, false, false, true]
+        );
+
+        let c = relexed.iter().find(|t| t.lexeme == "c").unwrap();
+        assert_eq!(*c.span(), Span::new(29, 30, 4, 5));
+        assert_eq!(*relexed[7].span(), Span::new(20, 21, 3, 2));
+    }
+
+    #[test]
+    fn relex_keeps_errors() {
+        // the error is in the reused part
+        let src = "a = 1; @ b;";
+        check(src, Edit::new(0..1, "aa"));
+        let relexed = apply_relex(scan(src), "aa = 1; @ b;", &Edit::new(0..1, "aa"));
+        assert_eq!(relexed.errors.len(), 1);
+        assert_eq!(relexed.errors[0].span, Span::new(8, 9, 1, 9));
+
+        // an unterminated string doesn't lose the tokens before it, nor the next relex
+        let edit = Edit::new(4..4, "\"");
+        check(src, edit.clone());
+        let broken = apply_relex(scan(src), &edit.apply(src), &edit);
+        assert_eq!(broken.tokens[0].lexeme, "a");
+        let fix = Edit::new(4..5, "");
+        let fixed = apply_relex(broken, src, &fix);
+        assert_eq!(summary(&fixed.tokens), summary(&scan(src).tokens));
+        assert_eq!(fixed.errors, scan(src).errors);
+    }
+
+    #[test]
+    fn relex_work_depends_on_edit_not_file() {
+        let rescanned = |lines: usize| {
+            let src = "var a = b + c; // x\n".repeat(lines);
+            // replace `b` on the last line
+            let at = src.len() - 12;
+            let edit = Edit::new(at..at + 1, "bee");
+            let mut old = scan(&src);
+            old.tokens
+                .iter_mut()
+                .for_each(|t| t.literal = LiteralValue::Str("old".into()));
+
+            let relexed = apply_relex(old, &edit.apply(&src), &edit);
+            relexed
+                .tokens
+                .iter()
+                .filter(|t| t.literal.to_string() != "old")
+                .count()
+        };
+
+        assert_eq!(rescanned(10), rescanned(10_000));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod dump;
+pub mod incremental;
+mod rlox;
+pub mod scanner;
+
+pub use crate::rlox::run;
@@ -1,57 +1,163 @@
-use shared::tokens;
+use rlox::dump::{self, DumpFormat};
+use rlox::scanner;
+use shared::diagnostics::Format;
+use shared::source_map::{FileId, SourceMap};
 use std::env;
+use std::fs;
 use std::io;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 
-#[macro_use]
-extern crate lazy_static;
+struct Args {
+    script: Option<String>,
+    dump: Option<DumpFormat>,
+    errors: Format,
+}
 
-mod rlox;
-pub mod scanner;
+fn args_valid() -> Result<Args, String> {
+    let mut args = Args {
+        script: None,
+        dump: None,
+        errors: Format::Terminal {
+            color: io::stderr().is_terminal(),
+        },
+    };
 
-fn args_valid() -> Result<Option<String>, String> {
-    let mut args: Vec<String> = env::args()
-        .skip(1) //skip executable name
-        .collect();
+    //skip executable name
+    for arg in env::args().skip(1) {
+        if let Some(format) = arg.strip_prefix("--dump-tokens=") {
+            args.dump = Some(format.parse()?);
+        } else if let Some(format) = arg.strip_prefix("--error-format=") {
+            args.errors = match format {
+                "terminal" => Format::Terminal {
+                    color: io::stderr().is_terminal(),
+                },
+                "plain" => Format::Plain,
+                "json" => Format::Json,
+                _ => return Err(format!("Unknown error format '{}'", format)),
+            };
+        } else if args.script.is_none() && !arg.starts_with("--") {
+            args.script = Some(arg);
+        } else {
+            return Err(format!("Unexpected argument '{}'", arg));
+        }
+    }
 
-    return if args.len() == 1 {
-        Ok(args.pop())
-    } else if args.is_empty() {
-        Ok(None)
-    } else {
-        Err("Invalid".to_string())
-    };
+    Ok(args)
+}
+
+/// Runs the program in `file` or, when asked to, dumps its tokens instead.
+fn exec(
+    sources: &SourceMap,
+    file: FileId,
+    dump: Option<DumpFormat>,
+) -> Result<(), Vec<scanner::ScanError>> {
+    match dump {
+        None => rlox::run(sources, file),
+        Some(format) => {
+            let errors = dump_tokens(sources, file, format);
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors)
+            }
+        }
+    }
+}
+
+/// Dumps the tokens of `file` to stdout. A reader that stops early, like `head`, ends the
+/// run quietly.
+fn dump_tokens(sources: &SourceMap, file: FileId, format: DumpFormat) -> Vec<scanner::ScanError> {
+    match dump::dump_tokens(sources, file, format, &mut io::stdout().lock()) {
+        Ok(errors) => errors,
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(e) => {
+            eprintln!("Could not write tokens: {}", e);
+            std::process::exit(74);
+        }
+    }
 }
 
-fn run_prompt() {
+fn run_prompt(dump: Option<DumpFormat>, errors: Format) {
+    let mut sources = SourceMap::new();
     let mut buffer = String::new();
 
     loop {
         print!("> ");
         io::stdout().flush().unwrap();
-        io::stdin().read_line(&mut buffer).unwrap();
-        rlox::run(&buffer);
+        match io::stdin().read_line(&mut buffer) {
+            // end of input, e.g. Ctrl-D
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Could not read the prompt input: {}", e);
+                std::process::exit(74);
+            }
+        }
+        // every line is kept as its own file
+        let file = sources.add("<repl>", buffer.as_str());
+        // errors in the REPL are reported but don't end the session
+        if let Err(e) = exec(&sources, file, dump) {
+            report(&e, &sources, errors);
+        }
         buffer.clear();
     }
 }
 
-fn run_file(path: &String) {
-    //TODO
-    println!("on path {}", path);
+fn run_file(path: &str, dump: Option<DumpFormat>, errors: Format) {
+    let bytes = match fs::read(path) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("Could not read '{}': {}", path, e);
+            std::process::exit(66);
+        }
+    };
+
+    let mut sources = SourceMap::new();
+    let program = match scanner::decode(&bytes) {
+        Ok(p) => p,
+        Err(mut e) => {
+            // everything before the bad bytes survives the lossy conversion unchanged and
+            // the bad bytes turn into a single replacement character
+            let file = sources.add(path, String::from_utf8_lossy(&bytes));
+            e.span.end = e.span.start + char::REPLACEMENT_CHARACTER.len_utf8();
+            e.span = e.span.in_file(file);
+            report(&[e], &sources, errors);
+            std::process::exit(65);
+        }
+    };
+
+    let file = sources.add(path, program);
+    if let Err(e) = exec(&sources, file, dump) {
+        report(&e, &sources, errors);
+        std::process::exit(65);
+    }
 }
 
-fn main() {
-    let had_error = false;
+fn report(errors: &[scanner::ScanError], sources: &SourceMap, format: Format) {
+    for e in errors {
+        eprint!("{}", e.to_diagnostic().render(sources, format));
+    }
+}
 
+fn main() {
     match args_valid() {
-        Ok(Some(ref s)) => {
-            run_file(s);
+        Ok(Args {
+            script: Some(ref s),
+            dump,
+            errors,
+        }) => {
+            run_file(s, dump, errors);
         }
-        Ok(None) => {
-            run_prompt();
+        Ok(Args {
+            script: None,
+            dump,
+            errors,
+        }) => {
+            run_prompt(dump, errors);
         }
-        Err(_e) => {
-            println!("Usage: rlox [script]");
+        Err(e) => {
+            eprintln!("{}", e);
+            println!("Usage: rlox [--dump-tokens=json|text] [--error-format=terminal|plain|json] [script]");
             std::process::exit(1);
         }
     };
@@ -1,10 +1,13 @@
 use crate::scanner;
+use shared::source_map::{FileId, SourceMap};
 
-pub fn run(program: &String) {
-    let s = scanner::Scanner::new(program);
-    let toks = s.scan_tokens();
+pub fn run(sources: &SourceMap, file: FileId) -> Result<(), Vec<scanner::ScanError>> {
+    let s = scanner::Scanner::new(sources, file, scanner::ScannerConfig::default());
+    let toks = s.scan_tokens()?;
 
     toks.iter().for_each(|t| {
         println!("{}", t);
-    })
+    });
+
+    Ok(())
 }
@@ -1,73 +1,313 @@
+use shared::diagnostics::Diagnostic;
+use shared::source_map::{line_breaks, FileId, SourceMap};
+use shared::symbols::Interner;
 use shared::tokens::TokenType::*;
-use shared::tokens::{LiteralValue, Token, TokenType};
+use shared::tokens::{
+    self, LiteralValue, Span, Token, TokenType, Trivia, TriviaKind, EXTENSION_KEYWORDS,
+};
 
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::ops::Deref;
-
-lazy_static! {
-    static ref KEYWORDS: HashMap<String, TokenType> = {
-        let mut keywords = HashMap::new();
-        keywords.insert("and".to_string(), AND);
-        keywords.insert("class".to_string(), CLASS);
-        keywords.insert("else".to_string(), ELSE);
-        keywords.insert("false".to_string(), FALSE);
-        keywords.insert("for".to_string(), FOR);
-        keywords.insert("fun".to_string(), FUN);
-        keywords.insert("if".to_string(), IF);
-        keywords.insert("nil".to_string(), NIL);
-        keywords.insert("or".to_string(), OR);
-        keywords.insert("print".to_string(), PRINT);
-        keywords.insert("return".to_string(), RETURN);
-        keywords.insert("super".to_string(), SUPER);
-        keywords.insert("this".to_string(), THIS);
-        keywords.insert("true".to_string(), TRUE);
-        keywords.insert("var".to_string(), VAR);
-        keywords.insert("while".to_string(), WHILE);
-        keywords
-    };
-}
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::str::CharIndices;
+use unicode_xid::UnicodeXID;
 
 static DIGITS: std::ops::RangeInclusive<char> = '0'..='9';
 
-static ALPHA_UPPER: std::ops::RangeInclusive<char> = 'A'..='Z';
-static ALPHA_LOWER: std::ops::RangeInclusive<char> = 'a'..='z';
+#[derive(Debug, PartialEq, Clone)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    UnterminatedComment,
+    UnterminatedInterpolation,
+    InvalidEscape(char),
+    InvalidUnicodeEscape,
+    InvalidNumber,
+    NumberOutOfRange,
+    InvalidUtf8,
+}
+
+/// Problem found while scanning, `span` covers the offending lexeme.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    pub span: Span,
+    pub lexeme: String,
+}
+
+impl Display for ScanErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanErrorKind::UnexpectedCharacter(c) => write!(f, "Unexpected character '{}'", c),
+            ScanErrorKind::UnterminatedString => write!(f, "Unterminated string"),
+            ScanErrorKind::UnterminatedComment => write!(f, "Unterminated block comment"),
+            ScanErrorKind::UnterminatedInterpolation => write!(f, "Unterminated interpolation"),
+            ScanErrorKind::InvalidEscape('\n' | '\r') => {
+                write!(f, "Invalid escape sequence '\\' at the end of a line")
+            }
+            ScanErrorKind::InvalidEscape(c) => write!(f, "Invalid escape sequence '\\{}'", c),
+            ScanErrorKind::InvalidUnicodeEscape => write!(f, "Invalid unicode escape"),
+            ScanErrorKind::InvalidNumber => write!(f, "Invalid number literal"),
+            ScanErrorKind::NumberOutOfRange => write!(f, "Number literal out of range"),
+            ScanErrorKind::InvalidUtf8 => write!(f, "Invalid UTF-8"),
+        }
+    }
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}:{}] Error at '{}': {}",
+            self.span.line, self.span.column, self.lexeme, self.kind
+        )
+    }
+}
+
+impl ScanError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let label = match self.kind {
+            ScanErrorKind::UnterminatedString => "string starts here",
+            ScanErrorKind::UnterminatedComment => "comment starts here",
+            ScanErrorKind::UnterminatedInterpolation => "never closed with a `}`",
+            _ => "",
+        };
+        let diagnostic = Diagnostic::error(self.kind.to_string()).with_primary(self.span, label);
+
+        match self.kind {
+            ScanErrorKind::InvalidEscape(_) => {
+                diagnostic.with_note("valid escapes are \\n \\t \\r \\0 \\\" \\\\ \\$ and \\u{...}")
+            }
+            _ => diagnostic,
+        }
+    }
+}
+
+/// Validates raw source bytes, reporting where the first invalid UTF-8 sequence is.
+/// The error's span is in `FileId::default()` since the bytes aren't a source yet.
+pub fn decode(bytes: &[u8]) -> Result<&str, ScanError> {
+    std::str::from_utf8(bytes).map_err(|e| {
+        // everything before the bad sequence is valid so it can be used to find the location
+        let valid = std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap();
+        let (breaks, line_txt) = line_breaks(valid.trim_start_matches('\u{FEFF}'));
+        let line = breaks + 1;
+        let bad_len = e.error_len().unwrap_or(bytes.len() - e.valid_up_to());
+        let bad = &bytes[e.valid_up_to()..e.valid_up_to() + bad_len];
+        let column = line_txt.chars().count() as u32 + 1;
+        ScanError {
+            kind: ScanErrorKind::InvalidUtf8,
+            span: Span::new(e.valid_up_to(), e.valid_up_to() + bad_len, line, column),
+            lexeme: bad.iter().map(|b| format!("\\x{:02x}", b)).collect(),
+        }
+    })
+}
+
+/// Language extensions on top of jlox. The default is plain jlox (plus block comments),
+/// `extended` turns everything on.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScannerConfig {
+    /// Reserved words besides the jlox ones, like `EXTENSION_KEYWORDS`.
+    pub keywords: Vec<(String, TokenType)>,
+    /// `%`
+    pub percent: bool,
+    /// `?`, `:` and `?:`
+    pub conditional: bool,
+    /// `=>`
+    pub fat_arrow: bool,
+    /// `// ...`
+    pub line_comments: bool,
+    /// `/* ... */`
+    pub block_comments: bool,
+    /// `# ...`
+    pub hash_comments: bool,
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        ScannerConfig {
+            keywords: vec![],
+            percent: false,
+            conditional: false,
+            fat_arrow: false,
+            line_comments: true,
+            block_comments: true,
+            hash_comments: false,
+        }
+    }
+}
+
+impl ScannerConfig {
+    pub fn extended() -> Self {
+        ScannerConfig {
+            keywords: EXTENSION_KEYWORDS
+                .iter()
+                .map(|(txt, tt)| (txt.to_string(), tt.clone()))
+                .collect(),
+            percent: true,
+            conditional: true,
+            fat_arrow: true,
+            hash_comments: true,
+            ..ScannerConfig::default()
+        }
+    }
+
+    fn keyword(&self, ident: &str) -> Option<TokenType> {
+        tokens::keyword(ident).or_else(|| {
+            self.keywords
+                .iter()
+                .find(|(txt, _)| txt == ident)
+                .map(|(_, tt)| tt.clone())
+        })
+    }
+}
+
+/// A `${` inside a string whose expression hasn't been closed yet.
+struct Interpolation {
+    // `{` seen inside the expression that still need a matching `}`
+    depth: u32,
+    // where the `${` is
+    start: usize,
+    line: u32,
+    column: u32,
+}
 
 struct InnerScanner<'a> {
-    src: &'a String,
+    src: &'a str,
+    file: FileId,
+    // where identifiers get their symbols
+    symbols: &'a RefCell<Interner>,
+    config: ScannerConfig,
+    chars: CharIndices<'a>,
+    // byte offset `chars` starts at, the indices it yields are relative to it
+    base: usize,
     start: usize,
+    // byte offset of the next char to be consumed
     current: usize,
     line: u32,
-    tokens: Vec<Token>,
+    // column of the next char to be consumed, counted in chars
+    column: u32,
+    // line and column where the current lexeme begins
+    start_line: u32,
+    start_column: u32,
+    // tokens and errors produced by the last scan_token call that haven't been handed out yet
+    pending: VecDeque<Result<Token<'a>, ScanError>>,
+    // open interpolations, innermost last
+    interpolations: Vec<Interpolation>,
+    // when set whitespace and comments are attached to the tokens instead of dropped
+    keep_trivia: bool,
+    // whether a leading BOM and shebang line were already dealt with
+    prelude_done: bool,
+    // trivia seen since the last token, becomes the leading trivia of the next one
+    trivia: Vec<Trivia<'a>>,
+    emitted_eof: bool,
 }
 
+/// Lazily scans the source, one token per `next` call. Errors are yielded in place of
+/// the lexeme that caused them and scanning carries on afterwards, the last item is
+/// always a single EOF token.
+/// Wrap it in `Peekable` to get the one token lookahead a parser needs.
 pub struct Scanner<'a> {
-    //exploring interior mutability so users don't need to bind the scanner as mut
-    inner: RefCell<InnerScanner<'a>>,
+    inner: InnerScanner<'a>,
 }
 
 impl<'a> Scanner<'a> {
-    pub fn new(src: &'a String) -> Self {
+    /// Scans `file`, every token span names it.
+    pub fn new(sources: &'a SourceMap, file: FileId, config: ScannerConfig) -> Self {
         Scanner {
-            inner: RefCell::new(InnerScanner::new(src)),
+            inner: InnerScanner::new(sources.src(file), file, sources.symbols(), config),
         }
     }
 
-    pub fn scan_tokens(&self) -> impl Deref<Target = Vec<Token>> + '_ {
-        self.inner.borrow_mut().scan_tokens();
+    /// Scans `src` starting at byte `offset`, which must be the start of a lexeme or
+    /// trivia outside of any string or interpolation, located at `line` and `column`.
+    pub(crate) fn resume(
+        src: &'a str,
+        file: FileId,
+        symbols: &'a RefCell<Interner>,
+        config: ScannerConfig,
+        offset: usize,
+        line: u32,
+        column: u32,
+    ) -> Self {
+        let mut inner = InnerScanner::new(src, file, symbols, config);
+        inner.chars = src[offset..].char_indices();
+        inner.base = offset;
+        inner.current = offset;
+        inner.line = line;
+        inner.column = column;
+        inner.prelude_done = offset > 0;
+        Scanner { inner }
+    }
+
+    /// True when the next token will be scanned with no string interpolation open, so
+    /// scanning from here only depends on the source that follows.
+    pub(crate) fn at_top_level(&self) -> bool {
+        self.inner.interpolations.is_empty() && self.inner.pending.is_empty()
+    }
 
-        std::cell::Ref::map(self.inner.borrow(), |d| &d.tokens)
+    /// Keeps whitespace, comments and text that failed to scan as trivia on the tokens,
+    /// see `Token` for where each piece ends up. Concatenating every token's leading trivia, lexeme and
+    /// trailing trivia gives back the original source.
+    pub fn with_trivia(mut self) -> Self {
+        self.inner.keep_trivia = true;
+        self
+    }
+
+    /// Scans the whole source. If anything went wrong every error found is returned
+    /// instead of the tokens.
+    pub fn scan_tokens(self) -> Result<Vec<Token<'a>>, Vec<ScanError>> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        for res in self {
+            match res {
+                Ok(t) => tokens.push(t),
+                Err(e) => errors.push(e),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl<'a> Iterator for Scanner<'a> {
+    type Item = Result<Token<'a>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_token()
     }
 }
 
 impl<'a> InnerScanner<'a> {
-    pub fn new(src: &'a String) -> Self {
+    pub fn new(
+        src: &'a str,
+        file: FileId,
+        symbols: &'a RefCell<Interner>,
+        config: ScannerConfig,
+    ) -> Self {
         InnerScanner {
             src,
+            file,
+            symbols,
+            config,
+            chars: src.char_indices(),
+            base: 0,
             start: 0,
             line: 1,
             current: 0,
-            tokens: vec![],
+            column: 1,
+            start_line: 1,
+            start_column: 1,
+            pending: VecDeque::new(),
+            interpolations: vec![],
+            keep_trivia: false,
+            prelude_done: false,
+            trivia: vec![],
+            emitted_eof: false,
         }
     }
 
@@ -81,14 +321,39 @@ impl<'a> InnerScanner<'a> {
         match c {
             '(' => self.add_token(LEFT_PAREN),
             ')' => self.add_token(RIGHT_PAREN),
-            '{' => self.add_token(LEFT_BRACE),
-            '}' => self.add_token(RIGHT_BRACE),
+            '{' => {
+                if let Some(open) = self.interpolations.last_mut() {
+                    open.depth += 1;
+                }
+                self.add_token(LEFT_BRACE);
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some(open) if open.depth == 0 => {
+                    // closes the interpolated expression, what follows is more string
+                    self.interpolations.pop();
+                    self.string();
+                }
+                Some(open) => {
+                    open.depth -= 1;
+                    self.add_token(RIGHT_BRACE);
+                }
+                None => self.add_token(RIGHT_BRACE),
+            },
             ',' => self.add_token(COMMA),
             '.' => self.add_token(DOT),
             '-' => self.add_token(MINUS),
             '+' => self.add_token(PLUS),
             ';' => self.add_token(SEMICOLON),
             '*' => self.add_token(STAR),
+            '%' if self.config.percent => self.add_token(PERCENT),
+            ':' if self.config.conditional => self.add_token(COLON),
+            '?' if self.config.conditional => {
+                if self.matching(':') {
+                    self.add_token(QUESTION_COLON);
+                } else {
+                    self.add_token(QUESTION);
+                }
+            }
             '!' => {
                 if self.matching('=') {
                     self.add_token(BANG_EQUAL);
@@ -99,6 +364,8 @@ impl<'a> InnerScanner<'a> {
             '=' => {
                 if self.matching('=') {
                     self.add_token(EQUAL_EQUAL);
+                } else if self.config.fat_arrow && self.matching('>') {
+                    self.add_token(FAT_ARROW);
                 } else {
                     self.add_token(EQUAL);
                 }
@@ -118,85 +385,206 @@ impl<'a> InnerScanner<'a> {
                 }
             }
             '/' => {
-                if self.matching('/') {
-                    // A comment goes until the end of the line.
-                    while self.peek() != '\n' && !self.is_at_end() {
-                        self.advance();
+                if self.config.line_comments && self.matching('/') {
+                    self.line_comment();
+                } else if self.config.block_comments && self.matching('*') {
+                    if self.block_comment() {
+                        self.add_trivia(TriviaKind::BlockComment);
+                    } else {
+                        self.add_trivia(TriviaKind::Skipped);
                     }
                 } else {
                     self.add_token(SLASH);
                 }
             }
-            ' ' | '\r' | '\t' => {} //Ignore whitespace
-            '\n' => self.line += 1,
+            '#' if self.config.hash_comments => self.line_comment(),
+            ' ' | '\t' => {
+                while matches!(self.peek(), ' ' | '\t') {
+                    self.advance();
+                }
+                self.add_trivia(TriviaKind::Whitespace);
+            }
+            '\r' | '\n' => {
+                self.line_break(c);
+                self.add_trivia(TriviaKind::Newline);
+            }
             '"' => self.string(),
             '0'..='9' => {
-                self.number();
+                self.number(c);
             }
 
             _ => {
-                if ALPHA_LOWER.contains(&c) || ALPHA_UPPER.contains(&c) || c == '_' {
+                if c.is_xid_start() || c == '_' {
                     self.identifier();
                 } else {
-                    println!("Unexpected character");
+                    self.error(ScanErrorKind::UnexpectedCharacter(c));
                 }
             }
         };
     }
 
     fn matching(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            return false;
-        }
-
-        let cur_char = self.src.as_bytes()[self.current] as char;
-        if cur_char != expected {
+        if self.is_at_end() || self.peek() != expected {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
 
         true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            '\0'
-        } else {
-            self.src.as_bytes()[self.current] as char
-        }
+        self.chars.clone().next().map_or('\0', |(_, c)| c)
     }
+
     fn peek_next(&self) -> char {
-        if (self.current + 1) > self.src.len() {
-            '\0'
-        } else {
-            self.src.as_bytes()[self.current + 1] as char
-        }
+        self.peek_nth(1)
     }
 
+    fn peek_nth(&self, n: usize) -> char {
+        self.chars.clone().nth(n).map_or('\0', |(_, c)| c)
+    }
+
+    /// Scans a string part that starts right after the opening `"` or the `}` closing an
+    /// interpolated expression.
+    /// `"a ${x} b ${y} c"` is scanned as `INTERPOLATION("a ")`, `x`, `INTERPOLATION(" b ")`,
+    /// `y` and `STRING(" c")`, which a parser can turn into a concatenation.
     fn string(&mut self) {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+        let src = self.src;
+        let content_start = self.start + 1;
+        // only allocated once an escape sequence shows up
+        let mut value: Option<String> = None;
+
+        loop {
+            if self.is_at_end() {
+                self.error(ScanErrorKind::UnterminatedString);
+                return;
+            }
+
+            let (offset, line, column) = (self.current, self.line, self.column);
+            match self.advance() {
+                '"' => break,
+                c @ ('\r' | '\n') => {
+                    let crlf = self.line_break(c);
+                    if let Some(v) = value.as_mut() {
+                        v.push(c);
+                        if crlf {
+                            v.push('\n');
+                        }
+                    }
+                }
+                '\\' => {
+                    let v = value.get_or_insert_with(|| String::from(&src[content_start..offset]));
+                    if let Some(c) = self.escape(offset, line, column) {
+                        v.push(c);
+                    }
+                }
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.interpolations.push(Interpolation {
+                        depth: 0,
+                        start: offset,
+                        line,
+                        column,
+                    });
+                    let literal = Self::string_value(value, &src[content_start..offset]);
+                    self.add_token_with_value(INTERPOLATION, literal);
+                    return;
+                }
+                c => {
+                    if let Some(v) = value.as_mut() {
+                        v.push(c);
+                    }
+                }
             }
-            self.advance();
         }
 
+        let literal = Self::string_value(value, &src[content_start..self.current - 1]);
+        self.add_token_with_value(STRING, literal);
+    }
+
+    fn string_value(escaped: Option<String>, raw: &'a str) -> LiteralValue<'a> {
+        match escaped {
+            Some(s) => LiteralValue::Str(Cow::Owned(s)),
+            None => LiteralValue::Str(Cow::Borrowed(raw)),
+        }
+    }
+
+    /// Decodes the escape sequence after a `\`, located at `offset`, `line` and `column`.
+    fn escape(&mut self, offset: usize, line: u32, column: u32) -> Option<char> {
         if self.is_at_end() {
-            println!("Unterminated string");
+            return None;
         }
 
-        //consume closing "
-        self.advance();
+        let decoded = match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            c @ ('"' | '\\' | '$') => Some(c),
+            'u' => self.unicode_escape(),
+            c @ ('\n' | '\r') => {
+                // the break still counts so everything after keeps its line
+                self.line_break(c);
+                let kind = ScanErrorKind::InvalidEscape(c);
+                self.error_at(kind, offset, self.current, line, column);
+                return None;
+            }
+            c => {
+                let kind = ScanErrorKind::InvalidEscape(c);
+                self.error_at(kind, offset, self.current, line, column);
+                return None;
+            }
+        };
 
-        let s = &self.src.as_str()[(self.start + 1)..(self.current - 1)];
-        self.add_token_with_value(STRING, LiteralValue::Str(String::from(s)));
+        if decoded.is_none() {
+            let kind = ScanErrorKind::InvalidUnicodeEscape;
+            self.error_at(kind, offset, self.current, line, column);
+        }
+        decoded
     }
 
-    fn number(&mut self) {
-        while DIGITS.contains(&self.peek()) {
+    /// Decodes the `{1F600}` part of a `\u{1F600}` escape.
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.matching('{') {
+            return None;
+        }
+
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
             self.advance();
         }
+        let digits = &self.src[digits_start..self.current];
+
+        if !self.matching('}') || digits.is_empty() || digits.len() > 6 {
+            return None;
+        }
+
+        u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+    }
+
+    /// Scans a number literal whose first digit was already consumed.
+    /// Integers become `Num` and may use `0x`, `0o` or `0b` prefixes, anything with a
+    /// fraction or an exponent becomes `NumFloat`. Digits can be separated with `_`.
+    fn number(&mut self, first: char) {
+        if first == '0' {
+            let radix = match self.peek() {
+                'x' | 'X' => Some(16),
+                'o' | 'O' => Some(8),
+                'b' | 'B' => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                //consume the prefix
+                self.advance();
+                self.radix_number(radix);
+                return;
+            }
+        }
+
+        self.digits(10);
 
         let mut is_float = false;
 
@@ -204,83 +592,325 @@ impl<'a> InnerScanner<'a> {
             //consume the . (dot)
             self.advance();
             is_float = true;
-            while DIGITS.contains(&self.peek()) {
+            self.digits(10);
+        }
+
+        if matches!(self.peek(), 'e' | 'E') {
+            let signed = matches!(self.peek_next(), '+' | '-');
+            let exp_digit = if signed {
+                self.peek_nth(2)
+            } else {
+                self.peek_next()
+            };
+
+            if DIGITS.contains(&exp_digit) {
                 self.advance();
+                if signed {
+                    self.advance();
+                }
+                is_float = true;
+                self.digits(10);
             }
         }
 
-        let s = &self.src.as_str()[self.start..self.current];
+        let s = &self.src[self.start..self.current];
+        if !Self::valid_separators(s, 10) {
+            self.error(ScanErrorKind::InvalidNumber);
+            return;
+        }
+        let s = s.replace('_', "");
 
         let literal = if is_float {
-            LiteralValue::NumFloat(s.parse::<f64>().unwrap())
+            match s.parse::<f64>() {
+                Ok(f) if f.is_finite() => LiteralValue::NumFloat(f),
+                _ => return self.error(ScanErrorKind::NumberOutOfRange),
+            }
         } else {
-            LiteralValue::Num(s.parse::<i64>().unwrap())
+            match s.parse::<i64>() {
+                Ok(n) => LiteralValue::Num(n),
+                Err(_) => return self.error(ScanErrorKind::NumberOutOfRange),
+            }
         };
 
         self.add_token_with_value(NUMBER, literal);
     }
 
+    fn radix_number(&mut self, radix: u32) {
+        let digits_start = self.current;
+        self.digits(radix);
+        let digits = &self.src[digits_start..self.current];
+
+        // things like 0b102 or 0xG are a typo rather than a number followed by an identifier
+        let mut valid = !digits.is_empty() && Self::valid_separators(digits, radix);
+        while self.peek().is_ascii_alphanumeric() {
+            self.advance();
+            valid = false;
+        }
+        if !valid {
+            self.error(ScanErrorKind::InvalidNumber);
+            return;
+        }
+
+        match i64::from_str_radix(&digits.replace('_', ""), radix) {
+            Ok(n) => self.add_token_with_value(NUMBER, LiteralValue::Num(n)),
+            Err(_) => self.error(ScanErrorKind::NumberOutOfRange),
+        }
+    }
+
+    fn digits(&mut self, radix: u32) {
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            self.advance();
+        }
+    }
+
+    /// A `_` is only allowed between two digits.
+    fn valid_separators(s: &str, radix: u32) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        chars.iter().enumerate().all(|(i, c)| {
+            *c != '_'
+                || (i > 0
+                    && chars[i - 1].is_digit(radix)
+                    && chars.get(i + 1).is_some_and(|n| n.is_digit(radix)))
+        })
+    }
+
     fn identifier(&mut self) {
         while Self::is_alpha_numeric(&self.peek()) {
             self.advance();
         }
 
-        let txt = &self.src.as_str()[self.start..self.current];
-        let r = KEYWORDS.get(txt);
-
-        let tt = if r.is_none() {
-            IDENTIFIER
-        } else {
-            r.unwrap().clone()
-        };
+        let txt = &self.src[self.start..self.current];
+        let tt = self.config.keyword(txt).unwrap_or(IDENTIFIER);
 
         self.add_token(tt);
     }
 
     fn is_alpha_numeric(c: &char) -> bool {
-        DIGITS.contains(c) || ALPHA_UPPER.contains(c) || ALPHA_LOWER.contains(c)
+        c.is_xid_continue()
     }
 
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.src.as_bytes()[self.current - 1] as char
+        match self.chars.next() {
+            Some((i, c)) => {
+                self.current = self.base + i + c.len_utf8();
+                self.column += 1;
+                c
+            }
+            None => '\0',
+        }
+    }
+
+    fn newline(&mut self) {
+        self.line += 1;
+        self.column = 1;
+    }
+
+    /// Handles a `\n`, `\r` or `\r\n` line break whose first char `c` was consumed.
+    /// Returns true for `\r\n`.
+    fn line_break(&mut self, c: char) -> bool {
+        let crlf = c == '\r' && self.matching('\n');
+        self.newline();
+        crlf
+    }
+
+    fn skip_to_line_end(&mut self) {
+        while !matches!(self.peek(), '\n' | '\r') && !self.is_at_end() {
+            self.advance();
+        }
+    }
+
+    fn line_comment(&mut self) {
+        // A comment goes until the end of the line.
+        self.skip_to_line_end();
+        self.add_trivia(TriviaKind::LineComment);
+    }
+
+    /// Skips a UTF-8 byte order mark and a `#!` line at the very start of the source.
+    fn prelude(&mut self) {
+        self.prelude_done = true;
+
+        if self.peek() == '\u{FEFF}' {
+            self.begin_lexeme();
+            self.advance();
+            // the mark isn't visible so it takes no column
+            self.column = 1;
+            self.add_trivia(TriviaKind::ByteOrderMark);
+        }
+
+        if self.peek() == '#' && self.peek_next() == '!' {
+            self.begin_lexeme();
+            self.skip_to_line_end();
+            self.add_trivia(TriviaKind::Shebang);
+        }
+    }
+
+    /// Skips a `/* ... */` comment, the opening `/*` must be already consumed.
+    /// Comments nest so every `/*` inside needs its own `*/`.
+    /// Returns false if the comment is never closed.
+    fn block_comment(&mut self) -> bool {
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                // point at the outermost opening instead of the whole comment
+                let kind = ScanErrorKind::UnterminatedComment;
+                self.error_at(
+                    kind,
+                    self.start,
+                    self.start + 2,
+                    self.start_line,
+                    self.start_column,
+                );
+                return false;
+            }
+
+            match self.advance() {
+                '/' if self.matching('*') => depth += 1,
+                '*' if self.matching('/') => depth -= 1,
+                c @ ('\r' | '\n') => {
+                    self.line_break(c);
+                }
+                _ => {}
+            }
+        }
+
+        true
+    }
+
+    /// Reports the whole lexeme, which doesn't become a token.
+    fn error(&mut self, kind: ScanErrorKind) {
+        let (start, end) = (self.start, self.current);
+        self.error_at(kind, start, end, self.start_line, self.start_column);
+        self.add_trivia(TriviaKind::Skipped);
+    }
+
+    /// Reports the source from byte `start` to `end`, which begins at `line` and `column`.
+    fn error_at(&mut self, kind: ScanErrorKind, start: usize, end: usize, line: u32, column: u32) {
+        self.pending.push_back(Err(ScanError {
+            kind,
+            span: Span::new(start, end, line, column).in_file(self.file),
+            lexeme: String::from(&self.src[start..end]),
+        }));
+    }
+
+    fn add_token_with_value(&mut self, tt: TokenType, literal: LiteralValue<'a>) {
+        let txt = &self.src[self.start..self.current];
+        let span = Span::new(self.start, self.current, self.start_line, self.start_column)
+            .in_file(self.file);
+        let mut tok = Token::with_span(tt, txt, span, literal);
+        if tok.tt == IDENTIFIER {
+            tok.symbol = Some(self.symbols.borrow_mut().intern(txt));
+        }
+        tok.leading_trivia = std::mem::take(&mut self.trivia);
+        self.pending.push_back(Ok(tok));
     }
 
-    fn add_token_with_value(&mut self, tt: TokenType, literal: LiteralValue) {
-        let txt = &self.src.as_str()[self.start..self.current];
-        let tok = Token::new(tt, String::from(txt), self.line, literal);
-        self.tokens.push(tok);
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        if !self.keep_trivia {
+            return;
+        }
+
+        self.trivia.push(Trivia {
+            kind,
+            text: Cow::Borrowed(&self.src[self.start..self.current]),
+            span: Span::new(self.start, self.current, self.start_line, self.start_column)
+                .in_file(self.file),
+        });
+    }
+
+    /// Collects the trivia left on the same line as `tok`.
+    fn trailing_trivia(&mut self, tok: &mut Token<'a>) {
+        while !self.is_at_end() {
+            let trivia_next = match self.peek() {
+                ' ' | '\t' => true,
+                '/' => match self.peek_next() {
+                    '/' => self.config.line_comments,
+                    '*' => self.config.block_comments,
+                    _ => false,
+                },
+                '#' => self.config.hash_comments,
+                _ => false,
+            };
+            if !trivia_next {
+                break;
+            }
+
+            self.begin_lexeme();
+            self.scan_token();
+        }
+
+        tok.trailing_trivia = std::mem::take(&mut self.trivia);
+    }
+
+    fn begin_lexeme(&mut self) {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
     }
 
     fn add_token(&mut self, tt: TokenType) {
         self.add_token_with_value(tt, LiteralValue::NoVal);
     }
 
-    fn scan_tokens(&mut self) -> &Vec<Token> {
-        while !self.is_at_end() {
-            self.start = self.current;
+    fn next_token(&mut self) -> Option<Result<Token<'a>, ScanError>> {
+        if !self.prelude_done {
+            self.prelude();
+        }
+
+        loop {
+            if let Some(mut res) = self.pending.pop_front() {
+                if let (true, true, Ok(tok)) = (self.keep_trivia, self.pending.is_empty(), &mut res)
+                {
+                    self.trailing_trivia(tok);
+                }
+                return Some(res);
+            }
+
+            if self.is_at_end() {
+                if let Some(open) = self.interpolations.pop() {
+                    let kind = ScanErrorKind::UnterminatedInterpolation;
+                    self.error_at(kind, open.start, open.start + 2, open.line, open.column);
+                    continue;
+                }
+                break;
+            }
+
+            self.begin_lexeme();
             self.scan_token();
         }
 
-        self.tokens.push(Token::new(
+        if self.emitted_eof {
+            return None;
+        }
+        self.emitted_eof = true;
+
+        let mut eof = Token::with_span(
             EOF,
-            "".to_string(),
-            self.line,
+            "",
+            Span::new(self.current, self.current, self.line, self.column).in_file(self.file),
             LiteralValue::NoVal,
-        ));
-
-        &self.tokens
+        );
+        eof.leading_trivia = std::mem::take(&mut self.trivia);
+        Some(Ok(eof))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use shared::diagnostics::Format;
+
+    fn test_source(prg: &str) -> (SourceMap, FileId) {
+        let mut sources = SourceMap::new();
+        let file = sources.add("test.lox", prg);
+        (sources, file)
+    }
+
     #[test]
     fn scan_string() {
         let prg = String::from("\"this is a rox string\"");
-        let s = Scanner::new(&prg);
-        let toks = s.scan_tokens();
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
 
         assert_eq!(toks.len(), 2);
 
@@ -292,7 +922,7 @@ mod tests {
             lexeme,
             literal,
             ..
-        } = toks.get(0).unwrap();
+        } = toks.first().unwrap();
         assert_eq!(tt, &STRING);
         assert_eq!(lexeme, "\"this is a rox string\"");
         if let LiteralValue::Str(s) = literal {
@@ -305,8 +935,9 @@ mod tests {
     #[test]
     fn scan_num() {
         let prg = String::from("992");
-        let s = Scanner::new(&prg);
-        let toks = s.scan_tokens();
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
 
         assert_eq!(toks.len(), 2);
 
@@ -318,7 +949,7 @@ mod tests {
             lexeme,
             literal,
             ..
-        } = toks.get(0).unwrap();
+        } = toks.first().unwrap();
         assert_eq!(tt, &NUMBER);
         assert_eq!(lexeme, "992");
 
@@ -332,8 +963,9 @@ mod tests {
     #[test]
     fn scan_num_float() {
         let prg = String::from("11.24");
-        let s = Scanner::new(&prg);
-        let toks = s.scan_tokens();
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
 
         assert_eq!(toks.len(), 2);
 
@@ -345,7 +977,7 @@ mod tests {
             lexeme,
             literal,
             ..
-        } = toks.get(0).unwrap();
+        } = toks.first().unwrap();
         assert_eq!(tt, &NUMBER);
         assert_eq!(lexeme, "11.24");
         if let LiteralValue::NumFloat(f) = literal {
@@ -358,8 +990,9 @@ mod tests {
     #[test]
     fn scan_identifier() {
         let prg = String::from("heh123");
-        let s = Scanner::new(&prg);
-        let toks = s.scan_tokens();
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
 
         assert_eq!(toks.len(), 2);
 
@@ -368,7 +1001,7 @@ mod tests {
             lexeme,
             literal,
             ..
-        } = toks.get(0).unwrap();
+        } = toks.first().unwrap();
         assert_eq!(tt, &IDENTIFIER);
         assert_eq!(lexeme, "heh123");
         if let LiteralValue::NoVal = literal {
@@ -380,13 +1013,655 @@ mod tests {
     #[test]
     fn scan_keyword() {
         let prg = String::from("class");
-        let s = Scanner::new(&prg);
-        let toks = s.scan_tokens();
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
 
         assert_eq!(toks.len(), 2);
 
-        let Token { tt, lexeme, .. } = toks.get(0).unwrap();
+        let Token { tt, lexeme, .. } = toks.first().unwrap();
         assert_eq!(tt, &CLASS);
         assert_eq!(lexeme, "class");
     }
+
+    #[test]
+    fn scan_errors() {
+        let prg = String::from("var a = 1;\n  @ #\n\"open");
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let errors = s.scan_tokens().err().unwrap();
+
+        assert_eq!(
+            errors,
+            vec![
+                ScanError {
+                    kind: ScanErrorKind::UnexpectedCharacter('@'),
+                    span: Span::new(13, 14, 2, 3).in_file(file),
+                    lexeme: "@".to_string(),
+                },
+                ScanError {
+                    kind: ScanErrorKind::UnexpectedCharacter('#'),
+                    span: Span::new(15, 16, 2, 5).in_file(file),
+                    lexeme: "#".to_string(),
+                },
+                ScanError {
+                    kind: ScanErrorKind::UnterminatedString,
+                    span: Span::new(17, 22, 3, 1).in_file(file),
+                    lexeme: "\"open".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn scan_spans() {
+        let prg = String::from("var a =\n  \"hi\";");
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
+
+        let spans: Vec<Span> = toks.iter().map(|t| *t.span()).collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 3, 1, 1),
+                Span::new(4, 5, 1, 5),
+                Span::new(6, 7, 1, 7),
+                Span::new(10, 14, 2, 3),
+                Span::new(14, 15, 2, 7),
+                Span::new(15, 15, 2, 8),
+            ]
+        );
+        assert_eq!(&prg[toks[3].span().start..toks[3].span().end], "\"hi\"");
+        assert_eq!(toks[3].line(), 2);
+    }
+
+    #[test]
+    fn scan_unicode() {
+        let prg = String::from("var café = \"héllo 😀\"; _ñ_1");
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let toks = s.scan_tokens().unwrap();
+
+        assert_eq!(toks.len(), 7);
+        assert_eq!(toks[1].tt, IDENTIFIER);
+        assert_eq!(toks[1].lexeme, "café");
+        if let LiteralValue::Str(s) = &toks[3].literal {
+            assert_eq!(s, "héllo 😀");
+        } else {
+            panic!("Expected String");
+        }
+        // columns count chars, offsets count bytes
+        assert_eq!(*toks[4].span(), Span::new(25, 26, 1, 21));
+        assert_eq!(toks[5].lexeme, "_ñ_1");
+        assert_eq!(toks[5].span().column, 23);
+    }
+
+    #[test]
+    fn scan_stray_code_point() {
+        let prg = String::from("a 😀 b");
+        let (sources, file) = test_source(&prg);
+        let s = Scanner::new(&sources, file, ScannerConfig::default());
+        let errors = s.scan_tokens().err().unwrap();
+
+        assert_eq!(
+            errors,
+            vec![ScanError {
+                kind: ScanErrorKind::UnexpectedCharacter('😀'),
+                span: Span::new(2, 6, 1, 3).in_file(file),
+                lexeme: "😀".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        assert_eq!(decode(b"print 1;").unwrap(), "print 1;");
+
+        let err = decode(b"var a;\nprint \"\xff\";").err().unwrap();
+        assert_eq!(
+            err,
+            ScanError {
+                kind: ScanErrorKind::InvalidUtf8,
+                span: Span::new(14, 15, 2, 8),
+                lexeme: "\\xff".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn scan_lazily() {
+        let prg = String::from("a @ b");
+        let (sources, file) = test_source(&prg);
+        let mut s = Scanner::new(&sources, file, ScannerConfig::default()).peekable();
+
+        assert_eq!(s.peek().unwrap().as_ref().unwrap().lexeme, "a");
+        assert_eq!(s.next().unwrap().unwrap().lexeme, "a");
+        assert_eq!(
+            s.next().unwrap().err().unwrap().kind,
+            ScanErrorKind::UnexpectedCharacter('@')
+        );
+        assert_eq!(s.next().unwrap().unwrap().lexeme, "b");
+        assert_eq!(s.next().unwrap().unwrap().tt, EOF);
+        assert!(s.next().is_none());
+        assert!(s.next().is_none());
+    }
+
+    #[test]
+    fn scan_borrowed() {
+        let owned: Vec<Token<'static>> = {
+            let prg = String::from("print \"hi\";");
+            let (sources, file) = test_source(&prg);
+            let toks = Scanner::new(&sources, file, ScannerConfig::default())
+                .scan_tokens()
+                .unwrap();
+
+            assert!(matches!(toks[0].lexeme, Cow::Borrowed("print")));
+            assert!(matches!(
+                &toks[1].literal,
+                LiteralValue::Str(Cow::Borrowed("hi"))
+            ));
+
+            toks.into_iter().map(Token::into_owned).collect()
+        };
+
+        assert_eq!(owned[0].lexeme, "print");
+        assert!(matches!(&owned[1].literal, LiteralValue::Str(Cow::Owned(s)) if s == "hi"));
+    }
+
+    #[test]
+    fn scan_block_comments() {
+        let prg = String::from("a /* one\n /* two */ still\n one */ b /**/ c");
+        let (sources, file) = test_source(&prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        let lexemes: Vec<&str> = toks.iter().map(|t| t.lexeme.as_ref()).collect();
+        assert_eq!(lexemes, vec!["a", "b", "c", ""]);
+        assert_eq!(toks[1].line(), 3);
+        assert_eq!(toks[1].span().column, 9);
+    }
+
+    #[test]
+    fn scan_unterminated_block_comment() {
+        let prg = String::from("a\n  /* open /* closed */\n\n");
+        let (sources, file) = test_source(&prg);
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            errors,
+            vec![ScanError {
+                kind: ScanErrorKind::UnterminatedComment,
+                span: Span::new(4, 6, 2, 3).in_file(file),
+                lexeme: "/*".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn scan_escapes() {
+        let prg = String::from(r#""tab\there \"quoted\" \\ \$ \u{1F600}\n" "plain""#);
+        let (sources, file) = test_source(&prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        assert!(matches!(
+            &toks[0].literal,
+            LiteralValue::Str(Cow::Owned(s)) if s == "tab\there \"quoted\" \\ $ 😀\n"
+        ));
+        assert!(matches!(
+            &toks[1].literal,
+            LiteralValue::Str(Cow::Borrowed("plain"))
+        ));
+    }
+
+    #[test]
+    fn scan_invalid_escapes() {
+        let prg = String::from(r#"print "a\qb \u{110000} \u{41";"#);
+        let (sources, file) = test_source(&prg);
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+
+        let found: Vec<(ScanErrorKind, u32, &str)> = errors
+            .iter()
+            .map(|e| (e.kind.clone(), e.span.column, e.lexeme.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (ScanErrorKind::InvalidEscape('q'), 9, "\\q"),
+                (ScanErrorKind::InvalidUnicodeEscape, 13, "\\u{110000}"),
+                (ScanErrorKind::InvalidUnicodeEscape, 24, "\\u{41"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escaped_line_break_keeps_lines() {
+        for prg in ["\"a\\\nb\" x", "\"a\\\r\nb\" x"] {
+            let (sources, file) = test_source(prg);
+            let lexed: Vec<_> = Scanner::new(&sources, file, ScannerConfig::default()).collect();
+
+            let error = lexed[0].as_ref().unwrap_err();
+            assert_eq!(
+                error.kind,
+                ScanErrorKind::InvalidEscape(prg.as_bytes()[3] as char)
+            );
+            assert_eq!((error.span.line, error.span.column), (1, 3));
+            assert!(error
+                .to_string()
+                .ends_with("Invalid escape sequence '\\' at the end of a line"));
+
+            let x = lexed[2].as_ref().unwrap();
+            assert_eq!(x.lexeme, "x");
+            assert_eq!((x.span().line, x.span().column), (2, 4));
+        }
+    }
+
+    #[test]
+    fn scan_interpolation() {
+        let prg = String::from(r#""Hello ${name}! ${ {a} } \${x}""#);
+        let (sources, file) = test_source(&prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        let found: Vec<(TokenType, &str)> = toks
+            .iter()
+            .map(|t| (t.tt.clone(), t.lexeme.as_ref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (INTERPOLATION, "\"Hello ${"),
+                (IDENTIFIER, "name"),
+                (INTERPOLATION, "}! ${"),
+                (LEFT_BRACE, "{"),
+                (IDENTIFIER, "a"),
+                (RIGHT_BRACE, "}"),
+                (STRING, "} \\${x}\""),
+                (EOF, ""),
+            ]
+        );
+
+        let parts: Vec<String> = toks
+            .iter()
+            .filter(|t| t.tt == INTERPOLATION || t.tt == STRING)
+            .map(|t| t.literal.to_string())
+            .collect();
+        assert_eq!(parts, vec!["Hello ", "! ", " ${x}"]);
+    }
+
+    #[test]
+    fn scan_unterminated_interpolation() {
+        let prg = String::from("\"a ${b");
+        let (sources, file) = test_source(&prg);
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            errors,
+            vec![ScanError {
+                kind: ScanErrorKind::UnterminatedInterpolation,
+                span: Span::new(3, 5, 1, 4).in_file(file),
+                lexeme: "${".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn scan_num_formats() {
+        let prg = String::from("0xFF 0b1010 0o17 1_000_000 1.5e-3 2E3 1e 0");
+        let (sources, file) = test_source(&prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        let found: Vec<String> = toks.iter().map(|t| t.literal.to_string()).collect();
+        assert_eq!(
+            found,
+            vec!["255", "10", "15", "1000000", "0.0015", "2000", "1", "nil", "0", "nil"]
+        );
+        assert!(matches!(toks[0].literal, LiteralValue::Num(255)));
+        assert!(matches!(toks[5].literal, LiteralValue::NumFloat(_)));
+        // `1e` is the number 1 followed by the identifier e
+        assert_eq!(toks[7].tt, IDENTIFIER);
+    }
+
+    #[test]
+    fn scan_num_errors() {
+        let prg = String::from("99999999999999999999\n0x8000000000000000 1e999 0b102 0x 1__0 2_");
+        let (sources, file) = test_source(&prg);
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+
+        let found: Vec<(ScanErrorKind, u32, u32, &str)> = errors
+            .iter()
+            .map(|e| {
+                (
+                    e.kind.clone(),
+                    e.span.line,
+                    e.span.column,
+                    e.lexeme.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    ScanErrorKind::NumberOutOfRange,
+                    1,
+                    1,
+                    "99999999999999999999"
+                ),
+                (ScanErrorKind::NumberOutOfRange, 2, 1, "0x8000000000000000"),
+                (ScanErrorKind::NumberOutOfRange, 2, 20, "1e999"),
+                (ScanErrorKind::InvalidNumber, 2, 26, "0b102"),
+                (ScanErrorKind::InvalidNumber, 2, 32, "0x"),
+                (ScanErrorKind::InvalidNumber, 2, 35, "1__0"),
+                (ScanErrorKind::InvalidNumber, 2, 40, "2_"),
+            ]
+        );
+    }
+
+    fn reconstruct(toks: &[Token]) -> String {
+        let mut out = String::new();
+        for t in toks {
+            t.leading_trivia
+                .iter()
+                .for_each(|tr| out.push_str(&tr.text));
+            out.push_str(&t.lexeme);
+            t.trailing_trivia
+                .iter()
+                .for_each(|tr| out.push_str(&tr.text));
+        }
+        out
+    }
+
+    #[test]
+    fn scan_trivia_round_trip() {
+        let programs = [
+            "",
+            "   \n\t// only a comment",
+            "var a = 1; // one\nvar b = /* inline */ a + 2;\n\n",
+            "fun greet(name) {\r\n  /* nested /* block */\n comment */ print \"hi ${name}!\";\r\n}\n",
+            "class A {\n  init() { this.x = 0xFF_FF; } // trailing\n}\n  // dangling\n",
+        ];
+
+        for prg in programs.iter() {
+            let (sources, file) = test_source(prg);
+            let toks = Scanner::new(&sources, file, ScannerConfig::default())
+                .with_trivia()
+                .scan_tokens()
+                .unwrap();
+            assert_eq!(&reconstruct(&toks), prg);
+        }
+
+        // what fails to scan is kept too
+        let errors = [
+            "a @ b",
+            "print 0b102 + 1_;",
+            "var s = \"bad \\q\";\nvar t = \"open",
+            "a /* never /* closed */",
+            "\"${1}",
+        ];
+
+        for prg in errors.iter() {
+            let (sources, file) = test_source(prg);
+            let toks: Vec<Token> = Scanner::new(&sources, file, ScannerConfig::default())
+                .with_trivia()
+                .filter_map(Result::ok)
+                .collect();
+            assert_eq!(&reconstruct(&toks), prg);
+        }
+    }
+
+    #[test]
+    fn scan_trivia_placement() {
+        let prg = String::from("a // one\n  b /* two */\n");
+        let (sources, file) = test_source(&prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .with_trivia()
+            .scan_tokens()
+            .unwrap();
+
+        let kinds =
+            |trivia: &Vec<Trivia>| -> Vec<TriviaKind> { trivia.iter().map(|t| t.kind).collect() };
+        assert!(toks[0].leading_trivia.is_empty());
+        assert_eq!(
+            kinds(&toks[0].trailing_trivia),
+            vec![TriviaKind::Whitespace, TriviaKind::LineComment]
+        );
+        assert_eq!(
+            kinds(&toks[1].leading_trivia),
+            vec![TriviaKind::Newline, TriviaKind::Whitespace]
+        );
+        assert_eq!(
+            kinds(&toks[1].trailing_trivia),
+            vec![TriviaKind::Whitespace, TriviaKind::BlockComment]
+        );
+        assert_eq!(kinds(&toks[2].leading_trivia), vec![TriviaKind::Newline]);
+        assert_eq!(toks[1].trailing_trivia[1].span, Span::new(13, 22, 2, 5));
+
+        // trivia is only kept on request
+        let (sources, file) = test_source(&prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+        assert!(toks
+            .iter()
+            .all(|t| t.leading_trivia.is_empty() && t.trailing_trivia.is_empty()));
+    }
+
+    fn token_types(src: &str, config: ScannerConfig) -> Vec<TokenType> {
+        let (sources, file) = test_source(src);
+        Scanner::new(&sources, file, config)
+            .filter_map(|t| t.ok())
+            .map(|t| t.tt)
+            .collect()
+    }
+
+    #[test]
+    fn scan_extended_dialect() {
+        let prg = "a % b ? c : d ?: e => break continue # note\n/* c */ x";
+
+        assert_eq!(
+            token_types(prg, ScannerConfig::extended()),
+            vec![
+                IDENTIFIER,
+                PERCENT,
+                IDENTIFIER,
+                QUESTION,
+                IDENTIFIER,
+                COLON,
+                IDENTIFIER,
+                QUESTION_COLON,
+                IDENTIFIER,
+                FAT_ARROW,
+                BREAK,
+                CONTINUE,
+                IDENTIFIER,
+                EOF
+            ]
+        );
+
+        // plain jlox doesn't know any of it
+        let (sources, file) = test_source(prg);
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+        let unexpected: Vec<String> = errors.iter().map(|e| e.lexeme.clone()).collect();
+        assert_eq!(unexpected, vec!["%", "?", ":", "?", ":", "#"]);
+        let keywords: Vec<TokenType> = token_types("break => continue", ScannerConfig::default());
+        assert_eq!(keywords, vec![IDENTIFIER, EQUAL, GREATER, IDENTIFIER, EOF]);
+    }
+
+    #[test]
+    fn scan_comment_styles() {
+        let config = ScannerConfig {
+            line_comments: false,
+            block_comments: false,
+            ..ScannerConfig::default()
+        };
+        assert_eq!(
+            token_types("a // b /* c", config),
+            vec![IDENTIFIER, SLASH, SLASH, IDENTIFIER, SLASH, STAR, IDENTIFIER, EOF]
+        );
+
+        let config = ScannerConfig {
+            keywords: vec![("unless".to_string(), IF)],
+            ..ScannerConfig::default()
+        };
+        assert_eq!(token_types("unless if", config), vec![IF, IF, EOF]);
+    }
+
+    #[test]
+    fn scan_shebang_and_bom() {
+        let prg = "\u{FEFF}#!/usr/bin/env rlox\r\nprint 1;";
+        let (sources, file) = test_source(prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        assert_eq!(toks[0].tt, PRINT);
+        assert_eq!(*toks[0].span(), Span::new(24, 29, 2, 1));
+
+        // only at the very start
+        let (sources, file) = test_source("print 1;\n#!/x");
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+        assert_eq!(errors[0].kind, ScanErrorKind::UnexpectedCharacter('#'));
+
+        let (sources, file) = test_source("\u{FEFF}a");
+        let bom_only = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(*bom_only[0].span(), Span::new(3, 4, 1, 1));
+
+        let (sources, file) = test_source(prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .with_trivia()
+            .scan_tokens()
+            .unwrap();
+        let kinds: Vec<TriviaKind> = toks[0].leading_trivia.iter().map(|t| t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TriviaKind::ByteOrderMark,
+                TriviaKind::Shebang,
+                TriviaKind::Newline
+            ]
+        );
+        assert_eq!(reconstruct(&toks), prg);
+    }
+
+    #[test]
+    fn scan_line_endings() {
+        let prg = "a\r\nb\rc\n\r\nd /* x\r\n */ e \"s\r\nt\" f";
+        let (sources, file) = test_source(prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        let positions: Vec<(&str, u32, u32)> = toks
+            .iter()
+            .map(|t| (t.lexeme.as_ref(), t.line(), t.span().column))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                ("a", 1, 1),
+                ("b", 2, 1),
+                ("c", 3, 1),
+                ("d", 5, 1),
+                ("e", 6, 5),
+                ("\"s\r\nt\"", 6, 7),
+                ("f", 7, 4),
+                ("", 7, 5),
+            ]
+        );
+        assert!(matches!(&toks[5].literal, LiteralValue::Str(s) if s == "s\r\nt"));
+
+        let (sources, file) = test_source(prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .with_trivia()
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(reconstruct(&toks), prg);
+    }
+
+    #[test]
+    fn scan_symbols() {
+        let prg = "var café = café + other; print café;";
+        let (sources, file) = test_source(prg);
+        let toks = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+
+        let cafe = sources.symbols().borrow_mut().intern("café");
+        let symbols: Vec<_> = toks.iter().map(|t| t.symbol).collect();
+        assert_eq!(symbols[0], None);
+        assert_eq!(symbols[1], Some(cafe));
+        assert_eq!(symbols[3], Some(cafe));
+        assert_eq!(symbols[8], Some(cafe));
+        assert_ne!(symbols[5], Some(cafe));
+        let names = sources.symbols().borrow();
+        assert_eq!(names.resolve(symbols[5].unwrap()), "other");
+        assert_eq!(names.len(), 2);
+    }
+
+    #[test]
+    fn spans_name_their_file() {
+        let mut sources = SourceMap::new();
+        sources.add("main.lox", "print 1;");
+        let lib = sources.add("lib.lox", "var a =\n  2;");
+
+        let toks = Scanner::new(&sources, lib, ScannerConfig::default())
+            .scan_tokens()
+            .unwrap();
+        assert!(toks.iter().all(|t| t.span().file == lib));
+
+        let two = toks[3].span();
+        let loc = sources.location(two.file, two.start);
+        assert_eq!((loc.line, loc.column), (two.line, two.column));
+        assert_eq!(sources.name(loc.file), "lib.lox");
+    }
+
+    #[test]
+    fn error_diagnostic() {
+        let prg = "print \"a\\qb\";";
+        let (sources, file) = test_source(prg);
+        let errors = Scanner::new(&sources, file, ScannerConfig::default())
+            .scan_tokens()
+            .err()
+            .unwrap();
+
+        let out = errors[0]
+            .to_diagnostic()
+            .render(&sources, Format::Terminal { color: false });
+        let expected = "\
+error: Invalid escape sequence '\\q'
+ --> test.lox:1:9
+  |
+1 | print \"a\\qb\";
+  |         ^^
+  = note: valid escapes are \\n \\t \\r \\0 \\\" \\\\ \\$ and \\u{...}
+";
+        assert_eq!(out, expected);
+    }
 }
@@ -0,0 +1,709 @@
+/// Rust's strict and reserved keywords, none of them can name generated items.
+pub const RUST_KEYWORDS: [&str; 52] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro",
+    "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Items every base module defines or uses besides its nodes, a node can't take their name.
+const GENERATED_NAMES: [&str; 7] = [
+    "Accept",
+    "AcceptMut",
+    "Visitor",
+    "VisitorMut",
+    "Box",
+    "Option",
+    "Vec",
+];
+
+/// A field of a node, `ty` is the type as written in the grammar.
+pub struct TypeName<'a> {
+    pub ty: &'a str,
+    pub name: &'a str,
+    pub line: u32,
+}
+
+/// A node kind of a base type, e.g. `Binary` of `Expr`.
+pub struct GrammarTy<'a> {
+    pub class_name: &'a str,
+    pub fields: Vec<TypeName<'a>>,
+    pub line: u32,
+}
+
+/// A `base Name { ... }` block, generated into its own module.
+pub struct Base<'a> {
+    pub name: &'a str,
+    pub types: Vec<GrammarTy<'a>>,
+    pub line: u32,
+}
+
+/// A `type Name = path` declaration, fields of type `Name` get the Rust type `path`.
+pub struct Alias<'a> {
+    pub name: &'a str,
+    pub path: &'a str,
+    pub line: u32,
+}
+
+pub struct Grammar<'a> {
+    pub bases: Vec<Base<'a>>,
+    pub aliases: Vec<Alias<'a>>,
+}
+
+/// A field type resolved against the grammar.
+#[derive(Debug, PartialEq)]
+pub enum FieldTy<'a> {
+    /// One of the base types declared in the grammar.
+    Base(&'a str),
+    Token,
+    Object,
+    Option(Box<FieldTy<'a>>),
+    /// `List<T>` or `Vec<T>`.
+    Vec(Box<FieldTy<'a>>),
+    Rc(Box<FieldTy<'a>>),
+    /// A Rust type used as written, either a path or a declared alias.
+    Path(&'a str),
+}
+
+impl<'a> FieldTy<'a> {
+    /// Base types mentioned anywhere in the type.
+    pub fn bases(&self) -> Vec<&'a str> {
+        match self {
+            FieldTy::Base(name) => vec![name],
+            FieldTy::Option(inner) | FieldTy::Vec(inner) | FieldTy::Rc(inner) => inner.bases(),
+            FieldTy::Token | FieldTy::Object | FieldTy::Path(_) => vec![],
+        }
+    }
+}
+
+const BUILTIN_TYPES: [&str; 6] = ["Token", "Object", "Option", "List", "Vec", "Rc"];
+
+impl<'a> Grammar<'a> {
+    pub fn base_names(&self) -> Vec<&'a str> {
+        self.bases.iter().map(|b| b.name).collect()
+    }
+
+    /// Resolved type of a field of this grammar, `parse` already made sure it resolves.
+    pub fn field_ty(&self, field: &TypeName<'a>) -> FieldTy<'a> {
+        self.resolve(field.ty)
+            .expect("field types are checked when parsing")
+    }
+
+    /// Resolves a field type as written in the grammar. Types containing `::` are Rust
+    /// paths and pass through untouched, generic arguments included.
+    pub fn resolve(&self, ty: &'a str) -> Result<FieldTy<'a>, String> {
+        if ty.contains("::") {
+            return Ok(FieldTy::Path(ty));
+        }
+
+        if let Some(open) = ty.find('<') {
+            let outer = ty[..open].trim();
+            let inner = match ty[open + 1..].strip_suffix('>') {
+                Some(inner) => inner.trim(),
+                None => return Err(format!("unknown type '{}'", ty)),
+            };
+            let wrap: fn(Box<FieldTy<'a>>) -> FieldTy<'a> = match outer {
+                "Option" => FieldTy::Option,
+                "List" | "Vec" => FieldTy::Vec,
+                "Rc" => FieldTy::Rc,
+                _ => {
+                    return Err(format!(
+                        "unknown generic type '{}', expected Option, List, Vec or Rc",
+                        outer
+                    ))
+                }
+            };
+            if split_args(inner).len() != 1 {
+                return Err(format!("'{}' takes a single type argument", outer));
+            }
+            return Ok(wrap(Box::new(self.resolve(inner)?)));
+        }
+
+        match ty {
+            "Token" => Ok(FieldTy::Token),
+            "Object" => Ok(FieldTy::Object),
+            "Option" | "List" | "Vec" | "Rc" => Err(format!("'{}' needs a type argument", ty)),
+            _ if self.bases.iter().any(|b| b.name == ty) => Ok(FieldTy::Base(ty)),
+            _ => match self.aliases.iter().find(|a| a.name == ty) {
+                Some(alias) => Ok(FieldTy::Path(alias.path)),
+                None => Err(format!("unknown type '{}'", ty)),
+            },
+        }
+    }
+}
+
+/// Splits generic arguments on the commas that aren't nested in another `<...>`.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+#[derive(Debug, PartialEq)]
+pub struct GrammarError {
+    pub line: u32,
+    pub message: String,
+}
+
+/// Parses a grammar file made of blocks like
+///
+/// ```text
+/// base Expr {
+///     Binary   : Expr left, Token operator, Expr right
+///     Grouping : Expr expression
+/// }
+/// ```
+///
+/// and declarations like `type Symbol = shared::symbols::Symbol` for Rust types used in
+/// fields.
+///
+/// Node kinds are separated by line breaks or `;`, a field list may continue on the next
+/// line after a `,`. `//` starts a comment. Every error found is returned, parsing picks
+/// up again on the next line.
+pub fn parse(src: &str) -> Result<Grammar<'_>, Vec<GrammarError>> {
+    let mut parser = Parser {
+        src,
+        pos: 0,
+        line: 1,
+        errors: vec![],
+    };
+
+    let mut grammar = Grammar {
+        bases: vec![],
+        aliases: vec![],
+    };
+    loop {
+        parser.skip_blank();
+        if parser.peek().is_none() {
+            break;
+        }
+
+        let line = parser.line;
+        let item = match parser.ident() {
+            Ok("base") => parser.base(line).map(|b| grammar.bases.push(b)),
+            Ok("type") => parser.alias(line).map(|a| grammar.aliases.push(a)),
+            Ok(other) => Err(parser.error(format!("expected 'base' or 'type', found '{}'", other))),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = item {
+            parser.errors.push(e);
+            // drop the rest of the broken item
+            while !matches!(parser.peek(), Some('\n') | None) {
+                if parser.bump() == Some('{') {
+                    while !matches!(parser.bump(), Some('}') | None) {}
+                }
+            }
+        }
+    }
+
+    check_duplicates(&grammar, &mut parser.errors);
+    check_types(&grammar, &mut parser.errors);
+    check_names(&grammar, &mut parser.errors);
+
+    if parser.errors.is_empty() {
+        Ok(grammar)
+    } else {
+        parser.errors.sort_by_key(|e| e.line);
+        Err(parser.errors)
+    }
+}
+
+fn check_duplicates(grammar: &Grammar, errors: &mut Vec<GrammarError>) {
+    let mut error = |line, message| errors.push(GrammarError { line, message });
+
+    let bases = &grammar.bases;
+    let aliases = &grammar.aliases;
+    for (i, alias) in aliases.iter().enumerate() {
+        let taken = BUILTIN_TYPES.contains(&alias.name)
+            || bases.iter().any(|b| b.name == alias.name)
+            || aliases[..i].iter().any(|a| a.name == alias.name);
+        if taken {
+            error(
+                alias.line,
+                format!("type '{}' is already declared", alias.name),
+            );
+        }
+    }
+
+    for (i, base) in bases.iter().enumerate() {
+        if bases[..i].iter().any(|b| b.name == base.name) {
+            error(base.line, format!("base '{}' is declared twice", base.name));
+        }
+
+        for (j, ty) in base.types.iter().enumerate() {
+            if base.types[..j]
+                .iter()
+                .any(|t| t.class_name == ty.class_name)
+            {
+                let message = format!("'{}' is declared twice in '{}'", ty.class_name, base.name);
+                error(ty.line, message);
+            }
+
+            for (k, field) in ty.fields.iter().enumerate() {
+                if ty.fields[..k].iter().any(|f| f.name == field.name) {
+                    let message = format!(
+                        "field '{}' is declared twice in '{}'",
+                        field.name, ty.class_name
+                    );
+                    error(field.line, message);
+                }
+            }
+        }
+    }
+}
+
+/// Names that would turn into Rust keywords. Node names are fine lowercased, the generator
+/// adds the base to those, but bases name modules and fields are used as they are. Node
+/// names also share their module with the base and the items generated for it.
+fn check_names(grammar: &Grammar, errors: &mut Vec<GrammarError>) {
+    let keyword = |name: &str| RUST_KEYWORDS.contains(&name);
+    let mut error = |line, message| errors.push(GrammarError { line, message });
+
+    for base in &grammar.bases {
+        if keyword(base.name) || keyword(&base.name.to_lowercase()) {
+            error(
+                base.line,
+                format!("base name '{}' is a Rust keyword", base.name),
+            );
+        }
+        for ty in &base.types {
+            if keyword(ty.class_name) {
+                error(
+                    ty.line,
+                    format!("node name '{}' is a Rust keyword", ty.class_name),
+                );
+            }
+            if GENERATED_NAMES.contains(&ty.class_name) {
+                error(
+                    ty.line,
+                    format!(
+                        "node name '{}' is taken by the generated code",
+                        ty.class_name
+                    ),
+                );
+            }
+            if ty.class_name.to_lowercase() == base.name.to_lowercase() {
+                error(
+                    ty.line,
+                    format!("node name '{}' is the name of its base", ty.class_name),
+                );
+            }
+            for field in ty.fields.iter().filter(|f| keyword(f.name)) {
+                error(
+                    field.line,
+                    format!(
+                        "field name '{}' of '{}' is a Rust keyword",
+                        field.name, ty.class_name
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn check_types(grammar: &Grammar, errors: &mut Vec<GrammarError>) {
+    for base in &grammar.bases {
+        for ty in &base.types {
+            for field in &ty.fields {
+                if let Err(e) = grammar.resolve(field.ty) {
+                    errors.push(GrammarError {
+                        line: field.line,
+                        message: format!("{} in field '{}' of '{}'", e, field.name, ty.class_name),
+                    });
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    line: u32,
+    errors: Vec<GrammarError>,
+}
+
+impl<'a> Parser<'a> {
+    /// The rest of a `type Name = path` line.
+    fn alias(&mut self, line: u32) -> Result<Alias<'a>, GrammarError> {
+        self.skip_inline();
+        let name = self.ident()?;
+        self.skip_inline();
+        self.expect('=')?;
+        self.skip_inline();
+        let path = self.ty()?;
+        self.skip_inline();
+        if !matches!(self.peek(), None | Some('\n') | Some('\r')) {
+            return Err(self.error_here("expected the end of the line after the type"));
+        }
+
+        Ok(Alias { name, path, line })
+    }
+
+    /// The rest of a `base Name { ... }` block.
+    fn base(&mut self, line: u32) -> Result<Base<'a>, GrammarError> {
+        self.skip_inline();
+        let name = self.ident()?;
+        self.skip_blank();
+        self.expect('{')?;
+
+        let mut types = vec![];
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => {
+                    let message = format!("'{}' is missing its closing '}}'", name);
+                    return Err(GrammarError { line, message });
+                }
+                Some('}') => {
+                    self.bump();
+                    break;
+                }
+                Some(';') => {
+                    self.bump();
+                }
+                Some(_) => match self.node() {
+                    Ok(ty) => types.push(ty),
+                    Err(e) => {
+                        self.errors.push(e);
+                        while !matches!(self.peek(), Some('\n') | Some('}') | None) {
+                            self.bump();
+                        }
+                    }
+                },
+            }
+        }
+
+        Ok(Base { name, types, line })
+    }
+
+    fn node(&mut self) -> Result<GrammarTy<'a>, GrammarError> {
+        let line = self.line;
+        let class_name = self.ident()?;
+        self.skip_inline();
+        self.expect(':')?;
+
+        let mut fields = vec![];
+        self.skip_inline();
+        while !self.at_node_end() {
+            let line = self.line;
+            let ty = self.ty()?;
+            self.skip_inline();
+            let name = self.ident()?;
+            fields.push(TypeName { ty, name, line });
+
+            self.skip_inline();
+            if self.peek() == Some(',') {
+                self.bump();
+                self.skip_blank();
+            } else if !self.at_node_end() {
+                return Err(self.error_here("expected ',' or the end of the line after a field"));
+            }
+        }
+
+        Ok(GrammarTy {
+            class_name,
+            fields,
+            line,
+        })
+    }
+
+    fn at_node_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            None | Some('\n') | Some('\r') | Some(';') | Some('}')
+        )
+    }
+
+    fn ident(&mut self) -> Result<&'a str, GrammarError> {
+        let start = self.pos;
+        if !matches!(self.peek(), Some(c) if c.is_ascii_alphabetic() || c == '_') {
+            return Err(self.error_here("expected a name"));
+        }
+        while matches!(self.peek(), Some(c) if c.is_ascii_alphanumeric() || c == '_') {
+            self.bump();
+        }
+        Ok(&self.src[start..self.pos])
+    }
+
+    /// A type, which may be a path and have generic arguments like `Option<Expr>`.
+    fn ty(&mut self) -> Result<&'a str, GrammarError> {
+        let start = self.pos;
+        self.ident()?;
+
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(c) if c.is_ascii_alphanumeric() || c == '_' || c == ':' => {}
+                Some('<') => depth += 1,
+                Some('>') if depth > 0 => depth -= 1,
+                Some(',') | Some(' ') | Some('\t') if depth > 0 => {}
+                _ => break,
+            }
+            self.bump();
+        }
+
+        if depth > 0 {
+            return Err(self.error_here("unclosed '<' in type"));
+        }
+        Ok(&self.src[start..self.pos])
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), GrammarError> {
+        if self.peek() == Some(c) {
+            self.bump();
+            Ok(())
+        } else {
+            Err(self.error_here(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn error(&self, message: String) -> GrammarError {
+        GrammarError {
+            line: self.line,
+            message,
+        }
+    }
+
+    /// Error mentioning what was found instead of what the grammar asks for.
+    fn error_here(&self, expected: &str) -> GrammarError {
+        match self.src[self.pos..].chars().next() {
+            None => self.error(format!("{}, found the end of the file", expected)),
+            Some('\n') | Some('\r') => {
+                self.error(format!("{}, found the end of the line", expected))
+            }
+            Some(c) => self.error(format!("{}, found '{}'", expected, c)),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    /// Skips spaces and comments up to the end of the line.
+    fn skip_inline(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ') | Some('\t') => {}
+                Some('/') if self.src[self.pos..].starts_with("//") => {
+                    while !matches!(self.peek(), Some('\n') | Some('\r') | None) {
+                        self.bump();
+                    }
+                    return;
+                }
+                _ => return,
+            }
+            self.bump();
+        }
+    }
+
+    /// Skips spaces, comments and line breaks.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_inline();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.bump();
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(src: &str) -> Vec<(u32, String)> {
+        parse(src)
+            .err()
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.line, e.message))
+            .collect()
+    }
+
+    #[test]
+    fn parse_blocks() {
+        let src = "// expressions\nbase Expr {\n    Binary   : Expr left, Token operator,\n               Expr right\n    Literal : Object value // a value\n}\n\nbase Stmt { Print: Expr expression; Block: Vec<Stmt> statements }\nbase Empty {}\n";
+        let bases = parse(src).unwrap().bases;
+
+        assert_eq!(bases.len(), 3);
+        assert_eq!((bases[0].name, bases[0].line), ("Expr", 2));
+        let binary = &bases[0].types[0];
+        assert_eq!(binary.class_name, "Binary");
+        let fields: Vec<_> = binary
+            .fields
+            .iter()
+            .map(|f| (f.ty, f.name, f.line))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Expr", "left", 3),
+                ("Token", "operator", 3),
+                ("Expr", "right", 4)
+            ]
+        );
+        assert_eq!(bases[0].types[1].line, 5);
+
+        let names: Vec<_> = bases[1].types.iter().map(|t| t.class_name).collect();
+        assert_eq!(names, vec!["Print", "Block"]);
+        assert_eq!(bases[1].types[1].fields[0].ty, "Vec<Stmt>");
+        assert!(bases[2].types.is_empty());
+    }
+
+    #[test]
+    fn resolve_types() {
+        let src = "type Symbol = shared::symbols::Symbol\nbase Expr { A: Expr a }\nbase Stmt {\n  B: std::collections::HashMap<String, Vec<Expr>> map, Rc<List<Stmt>> body\n  C: Option<Symbol> name\n}";
+        let grammar = parse(src).unwrap();
+
+        assert_eq!(grammar.aliases[0].path, "shared::symbols::Symbol");
+        let b = &grammar.bases[1].types[0];
+        assert_eq!(
+            b.fields[0].ty,
+            "std::collections::HashMap<String, Vec<Expr>>"
+        );
+        let resolved: Vec<FieldTy> = grammar.bases[1]
+            .types
+            .iter()
+            .flat_map(|t| t.fields.iter())
+            .map(|f| grammar.resolve(f.ty).unwrap())
+            .collect();
+        assert_eq!(
+            resolved,
+            vec![
+                FieldTy::Path("std::collections::HashMap<String, Vec<Expr>>"),
+                FieldTy::Rc(Box::new(FieldTy::Vec(Box::new(FieldTy::Base("Stmt"))))),
+                FieldTy::Option(Box::new(FieldTy::Path("shared::symbols::Symbol"))),
+            ]
+        );
+        assert_eq!(
+            grammar.resolve("Option<Vec<Expr>>").unwrap().bases(),
+            vec!["Expr"]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let src = "base Expr {\n  Binary Expr left\n  Unary: Token operator Expr right\n  Literal: Object value\n  Literal: Option<Object value\n}\nbsae Stmt { }\nbase Expr {\n  Print: Expr a, Expr a\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (2, "expected ':', found 'E'".to_string()),
+                (
+                    3,
+                    "expected ',' or the end of the line after a field, found 'E'".to_string()
+                ),
+                (
+                    5,
+                    "unclosed '<' in type, found the end of the line".to_string()
+                ),
+                (7, "expected 'base' or 'type', found 'bsae'".to_string()),
+                (8, "'Expr' is missing its closing '}'".to_string()),
+            ]
+        );
+
+        assert_eq!(
+            errors("base Expr { A: Expr a }\nbase Expr {\n  B: Expr b, Token b\n  B: Expr c\n}"),
+            vec![
+                (2, "base 'Expr' is declared twice".to_string()),
+                (3, "field 'b' is declared twice in 'B'".to_string()),
+                (4, "'B' is declared twice in 'Expr'".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn keyword_errors() {
+        let src = "base Type {\n  Self: Token a\n  Super: Token keyword, Token type, Token in\n}";
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, "base name 'Type' is a Rust keyword".to_string()),
+                (2, "node name 'Self' is a Rust keyword".to_string()),
+                (
+                    3,
+                    "field name 'type' of 'Super' is a Rust keyword".to_string()
+                ),
+                (
+                    3,
+                    "field name 'in' of 'Super' is a Rust keyword".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn generated_name_errors() {
+        let src = "base Expr {\n  Lit: Object v\n  Accept: Token t\n  VisitorMut: Token t\n  Option: Token t\n  Expr: Token t\n}";
+        assert_eq!(
+            errors(src),
+            vec![
+                (
+                    3,
+                    "node name 'Accept' is taken by the generated code".to_string()
+                ),
+                (
+                    4,
+                    "node name 'VisitorMut' is taken by the generated code".to_string()
+                ),
+                (
+                    5,
+                    "node name 'Option' is taken by the generated code".to_string()
+                ),
+                (6, "node name 'Expr' is the name of its base".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn type_errors() {
+        let src = "type Token = x::Token\ntype Name = \nbase Expr {\n  A: Exp a, Vec b, Option<Expr, Token> c\n  B: HashMap<String, Expr> d\n  C: Box<Expr> e, Vec<Foo> f\n}";
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, "type 'Token' is already declared".to_string()),
+                (2, "expected a name, found the end of the line".to_string()),
+                (4, "unknown type 'Exp' in field 'a' of 'A'".to_string()),
+                (4, "'Vec' needs a type argument in field 'b' of 'A'".to_string()),
+                (4, "'Option' takes a single type argument in field 'c' of 'A'".to_string()),
+                (
+                    5,
+                    "unknown generic type 'HashMap', expected Option, List, Vec or Rc in field 'd' of 'B'"
+                        .to_string()
+                ),
+                (
+                    6,
+                    "unknown generic type 'Box', expected Option, List, Vec or Rc in field 'e' of 'C'"
+                        .to_string()
+                ),
+                (6, "unknown type 'Foo' in field 'f' of 'C'".to_string()),
+            ]
+        );
+    }
+}
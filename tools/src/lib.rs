@@ -0,0 +1,595 @@
+//! Turns the syntax tree grammar (see `grammar::parse`) into Rust modules, either one
+//! file per base for the `generate_ast` binary or one file of inline modules for build
+//! scripts.
+
+use grammar::{Grammar, GrammarTy};
+use std::fs::File;
+use std::io;
+use std::io::Write;
+
+pub mod grammar;
+
+type IORes = io::Result<()>;
+
+/// Writes `<out_dir>/<base>.rs` for `base_name`, `define_mod` declares it.
+pub fn define_ast(out_dir: &str, base_name: &str, types: &[GrammarTy], grammar: &Grammar) -> IORes {
+    let p = std::path::PathBuf::from(out_dir);
+    let p = p.join(std::path::PathBuf::from(format!(
+        "{}.rs",
+        base_name.to_lowercase()
+    )));
+
+    let mut f = File::create(p)?;
+    define_base(&mut f, base_name, types, grammar)
+}
+
+/// Writes every base as an inline `pub mod`, meant to be `include!`d in the module the
+/// bases refer to each other from.
+pub fn define_inline_mods(out: &mut dyn Write, grammar: &Grammar) -> IORes {
+    for base in &grammar.bases {
+        out.write_all(format!("pub mod {} {{\n", base.name.to_lowercase()).as_bytes())?;
+        define_base(out, base.name, &base.types, grammar)?;
+        out.write_all("}\n".as_bytes())?;
+    }
+    define_fold(out, grammar)?;
+    define_walker(out, grammar)
+}
+
+fn define_base(
+    f: &mut dyn Write,
+    base_name: &str,
+    types: &[GrammarTy],
+    grammar: &Grammar,
+) -> IORes {
+    f.write_all("use shared::tokens;\n".as_bytes())?;
+
+    define_enum_type(f, base_name, types)?;
+
+    define_struct_types(f, base_name, types, grammar)?;
+
+    define_constructors(f, base_name, types, grammar)?;
+
+    define_accept_trait(f, base_name, types, Access::Shared)?;
+
+    define_visitor(f, base_name, types, Access::Shared)?;
+
+    define_accept_trait(f, base_name, types, Access::Mut)?;
+
+    define_visitor(f, base_name, types, Access::Mut)?;
+
+    define_walk_fns(f, base_name, types, grammar)
+}
+
+/// How a visitor gets at its own state, `Mut` generates the `AcceptMut`/`VisitorMut` pair.
+#[derive(Clone, Copy)]
+enum Access {
+    Shared,
+    Mut,
+}
+
+impl Access {
+    fn suffix(self) -> &'static str {
+        match self {
+            Access::Shared => "",
+            Access::Mut => "Mut",
+        }
+    }
+
+    fn method(self) -> &'static str {
+        match self {
+            Access::Shared => "accept",
+            Access::Mut => "accept_mut",
+        }
+    }
+
+    fn reference(self) -> &'static str {
+        match self {
+            Access::Shared => "&",
+            Access::Mut => "&mut ",
+        }
+    }
+}
+
+fn define_accept_trait(
+    file: &mut dyn Write,
+    base_name: &str,
+    types: &[GrammarTy],
+    access: Access,
+) -> IORes {
+    let signature = format!(
+        "fn {}(&self, visitor: {}dyn Visitor{}<R>) -> R",
+        access.method(),
+        access.reference(),
+        access.suffix()
+    );
+    file.write_all(
+        format!(
+            "pub trait Accept{}<R> {{\n{:4}{};\n}}\n",
+            access.suffix(),
+            " ",
+            signature
+        )
+        .as_bytes(),
+    )?;
+
+    file.write_all(
+        format!(
+            "impl<R> Accept{}<R> for {} {{\n",
+            access.suffix(),
+            base_name
+        )
+        .as_bytes(),
+    )?;
+    file.write_all(format!("{:4}{} {{\n", " ", signature).as_bytes())?;
+    file.write_all(format!("{:8}match self {{\n", " ").as_bytes())?;
+
+    for typ in types {
+        file.write_all(
+            format!(
+                "{:12}{}::{}(a) => visitor.visit_{}_{}(a),\n",
+                " ",
+                base_name,
+                typ.class_name,
+                typ.class_name.to_lowercase(),
+                base_name.to_lowercase(),
+            )
+            .as_bytes(),
+        )?;
+    }
+
+    file.write_all(format!("{:8}}}\n{:4}}}\n}}\n", " ", " ").as_bytes())
+}
+
+const DERIVES: &str = "#[derive(Debug, Clone, PartialEq)]\n";
+
+fn define_enum_type(file: &mut dyn Write, base_name: &str, types: &[GrammarTy]) -> IORes {
+    file.write_all(DERIVES.as_bytes())?;
+    file.write_all(format!("pub enum {} {{ \n", base_name).as_bytes())?;
+    for ty in types {
+        file.write_all(format!("    {}({}),\n", ty.class_name, ty.class_name).as_bytes())?;
+    }
+    file.write_all("} \n".as_bytes())
+}
+
+fn define_struct_types(
+    file: &mut dyn Write,
+    base_name: &str,
+    types: &[GrammarTy],
+    grammar: &Grammar,
+) -> IORes {
+    for typ in types {
+        let fields = &typ.fields;
+
+        file.write_all(DERIVES.as_bytes())?;
+        file.write_all(format!("pub struct {} {{\n", typ.class_name).as_bytes())?;
+
+        for field in fields {
+            let ty = ty_map::map_to_rs_type(&grammar.field_ty(field), base_name);
+            file.write_all(format!("{:4}pub {}: {},\n", " ", field.name, ty).as_bytes())?;
+        }
+        file.write_all("}\n".as_bytes())?;
+    }
+    file.write_all("\n".as_bytes())
+}
+
+/// A constructor per node on the base type, taking the children unboxed so trees can
+/// be written as `Expr::binary(left, operator, right)`.
+fn define_constructors(
+    file: &mut dyn Write,
+    base_name: &str,
+    types: &[GrammarTy],
+    grammar: &Grammar,
+) -> IORes {
+    file.write_all(format!("impl {} {{\n", base_name).as_bytes())?;
+    for typ in types {
+        let mut params = vec![];
+        let mut values = vec![];
+        for field in &typ.fields {
+            let ty = grammar.field_ty(field);
+            params.push(format!(
+                "{}: {}",
+                field.name,
+                ty_map::param_type(&ty, base_name)
+            ));
+            values.push(match ty_map::box_code(&ty, field.name) {
+                Some(value) => format!("{}: {}", field.name, value),
+                None => field.name.to_string(),
+            });
+        }
+
+        file.write_all(
+            format!(
+                "{:4}pub fn {}({}) -> Self {{\n{:8}{}::{}({} {{ {} }})\n{:4}}}\n",
+                " ",
+                param_name(typ.class_name, base_name),
+                params.join(", "),
+                " ",
+                base_name,
+                typ.class_name,
+                typ.class_name,
+                values.join(", "),
+                " "
+            )
+            .as_bytes(),
+        )?;
+    }
+    file.write_all("}\n\n".as_bytes())
+}
+
+fn define_visitor(
+    file: &mut dyn Write,
+    base_name: &str,
+    types: &[GrammarTy],
+    access: Access,
+) -> IORes {
+    file.write_all(format!("pub trait Visitor{}<R> {{\n", access.suffix()).as_bytes())?;
+    for ty in types {
+        file.write_all(
+            format!(
+                "{:4}fn visit_{}_{}({}self, {}: &{}) -> R;\n",
+                " ",
+                ty.class_name.to_lowercase(),
+                base_name.to_lowercase(),
+                access.reference(),
+                param_name(ty.class_name, base_name),
+                ty.class_name,
+            )
+            .as_bytes(),
+        )?;
+    }
+    file.write_all("}\n".as_bytes())
+}
+
+/// One trait rebuilding trees of every base by value, written next to the base modules.
+/// Every method defaults to folding the children and putting the node back together.
+/// Bases refer to each other freely, so a trait per base would need cyclic supertraits.
+fn define_fold(file: &mut dyn Write, grammar: &Grammar) -> IORes {
+    file.write_all("pub trait Fold {\n".as_bytes())?;
+    for b in &grammar.bases {
+        let (base_name, types) = (b.name, &b.types);
+        let base = base_name.to_lowercase();
+
+        file.write_all(
+            format!(
+                "{:4}fn fold_{}(&mut self, {}: {}::{}) -> {}::{} {{\n{:8}match {} {{\n",
+                " ", base, base, base, base_name, base, base_name, " ", base
+            )
+            .as_bytes(),
+        )?;
+        for ty in types {
+            file.write_all(
+                format!(
+                    "{:12}{}::{}::{}(a) => self.fold_{}_{}(a),\n",
+                    " ",
+                    base,
+                    base_name,
+                    ty.class_name,
+                    ty.class_name.to_lowercase(),
+                    base
+                )
+                .as_bytes(),
+            )?;
+        }
+        file.write_all(format!("{:8}}}\n{:4}}}\n", " ", " ").as_bytes())?;
+
+        for ty in types {
+            let param = param_name(ty.class_name, base_name);
+            file.write_all(
+                format!(
+                    "{:4}fn fold_{}_{}(&mut self, {}: {}::{}) -> {}::{} {{\n",
+                    " ",
+                    ty.class_name.to_lowercase(),
+                    base,
+                    param,
+                    base,
+                    ty.class_name,
+                    base,
+                    base_name
+                )
+                .as_bytes(),
+            )?;
+
+            let folded: Vec<(&str, Option<String>)> = ty
+                .fields
+                .iter()
+                .map(|f| {
+                    let value = format!("{}.{}", param, f.name);
+                    let ty = grammar.field_ty(f);
+                    (f.name, ty_map::fold_code(&ty, &value))
+                })
+                .collect();
+
+            if folded.iter().all(|(_, code)| code.is_none()) {
+                file.write_all(
+                    format!(
+                        "{:8}{}::{}::{}({})\n",
+                        " ", base, base_name, ty.class_name, param
+                    )
+                    .as_bytes(),
+                )?;
+            } else {
+                file.write_all(
+                    format!(
+                        "{:8}{}::{}::{}({}::{} {{\n",
+                        " ", base, base_name, ty.class_name, base, ty.class_name
+                    )
+                    .as_bytes(),
+                )?;
+                for (name, code) in folded {
+                    let code = code.unwrap_or_else(|| format!("{}.{}", param, name));
+                    file.write_all(format!("{:12}{}: {},\n", " ", name, code).as_bytes())?;
+                }
+                file.write_all(format!("{:8}}})\n", " ").as_bytes())?;
+            }
+            file.write_all(format!("{:4}}}\n", " ").as_bytes())?;
+        }
+    }
+    file.write_all("}\n".as_bytes())
+}
+
+/// Name for a parameter or constructor of a `class_name` node, node names like `If` or
+/// `Super` would turn into keywords otherwise.
+fn param_name(class_name: &str, base_name: &str) -> String {
+    let name = class_name.to_lowercase();
+    if grammar::RUST_KEYWORDS.contains(&name.as_str()) {
+        format!("{}_{}", name, base_name.to_lowercase())
+    } else {
+        name
+    }
+}
+
+/// One `Walker` trait for every base, written next to the base modules like `Fold`. Its
+/// `walk_<base>` and `walk_<node>_<base>` methods default to the `walk_*` functions of
+/// each base module, which visit the children of a node. Implementors override only the
+/// nodes they care about. The names keep clear of `Visitor`, a type may implement both.
+fn define_walker(file: &mut dyn Write, grammar: &Grammar) -> IORes {
+    file.write_all("pub trait Walker {\n".as_bytes())?;
+    for b in &grammar.bases {
+        let base = b.name.to_lowercase();
+        file.write_all(
+            format!(
+                "{:4}fn walk_{}(&mut self, {}: &{}::{}) {{\n{:8}{}::walk_{}(self, {})\n{:4}}}\n",
+                " ", base, base, base, b.name, " ", base, base, base, " "
+            )
+            .as_bytes(),
+        )?;
+        for ty in &b.types {
+            let name = ty.class_name.to_lowercase();
+            let param = param_name(ty.class_name, b.name);
+            file.write_all(
+                format!(
+                    "{:4}fn walk_{}_{}(&mut self, {}: &{}::{}) {{\n{:8}{}::walk_{}(self, {})\n{:4}}}\n",
+                    " ", name, base, param, base, ty.class_name, " ", base, name, param, " "
+                )
+                .as_bytes(),
+            )?;
+        }
+    }
+    file.write_all("}\n".as_bytes())
+}
+
+/// The `walk_*` functions of `base_name`, visiting the children of a node.
+fn define_walk_fns(
+    file: &mut dyn Write,
+    base_name: &str,
+    types: &[GrammarTy],
+    grammar: &Grammar,
+) -> IORes {
+    let base = base_name.to_lowercase();
+    file.write_all(
+        format!(
+            "pub fn walk_{}<W: super::Walker + ?Sized>(walker: &mut W, {}: &{}) {{\n{:4}match {} {{\n",
+            base, base, base_name, " ", base
+        )
+        .as_bytes(),
+    )?;
+    for ty in types {
+        file.write_all(
+            format!(
+                "{:8}{}::{}(a) => walker.walk_{}_{}(a),\n",
+                " ",
+                base_name,
+                ty.class_name,
+                ty.class_name.to_lowercase(),
+                base
+            )
+            .as_bytes(),
+        )?;
+    }
+    file.write_all(format!("{:4}}}\n}}\n", " ").as_bytes())?;
+
+    for ty in types {
+        let param = param_name(ty.class_name, base_name);
+        let walks: Vec<String> = ty
+            .fields
+            .iter()
+            .filter_map(|f| {
+                let value = format!("{}.{}", param, f.name);
+                ty_map::walk_code(&grammar.field_ty(f), &value)
+            })
+            .collect();
+
+        // nothing to walk into leaves both parameters unused
+        let unused = if walks.is_empty() { "_" } else { "" };
+        file.write_all(
+            format!(
+                "pub fn walk_{}<W: super::Walker + ?Sized>({}walker: &mut W, {}{}: &{}) {{\n",
+                ty.class_name.to_lowercase(),
+                unused,
+                unused,
+                param,
+                ty.class_name
+            )
+            .as_bytes(),
+        )?;
+        for walk in walks {
+            file.write_all(format!("{:4}{}\n", " ", walk).as_bytes())?;
+        }
+        file.write_all("}\n".as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Declares a module per base type, next to the traits covering all of them.
+pub fn define_mod(out_dir: &str, grammar: &Grammar) -> IORes {
+    let p = std::path::PathBuf::from(out_dir).join("mod.rs");
+    let mut f = File::create(p)?;
+
+    for base in grammar.base_names() {
+        f.write_all(format!("pub mod {};\n", base.to_lowercase()).as_bytes())?;
+    }
+    define_fold(&mut f, grammar)?;
+    define_walker(&mut f, grammar)
+}
+
+mod ty_map {
+    use crate::grammar::FieldTy;
+
+    /// Rust type for a field of type `ty` in a `base_name` node.
+    pub fn map_to_rs_type(ty: &FieldTy, base_name: &str) -> String {
+        map(ty, base_name, true)
+    }
+
+    /// Rust type a constructor takes for a field of type `ty`, nodes come unboxed.
+    pub fn param_type(ty: &FieldTy, base_name: &str) -> String {
+        map(ty, base_name, false)
+    }
+
+    /// Code boxing a constructor argument `value` of type `ty` for its field, `None`
+    /// when it is stored as is.
+    pub fn box_code(ty: &FieldTy, value: &str) -> Option<String> {
+        match ty {
+            FieldTy::Base(_) => Some(format!("Box::new({})", value)),
+            FieldTy::Option(inner) if matches!(**inner, FieldTy::Base(_)) => {
+                Some(format!("{}.map(Box::new)", value))
+            }
+            FieldTy::Option(inner) => {
+                let inner = box_code(inner, "v")?;
+                Some(format!("{}.map(|v| {})", value, inner))
+            }
+            _ => None,
+        }
+    }
+
+    // `boxed` tells whether a node here needs a box to keep the type finite, a `Vec` or
+    // `Rc` already puts its contents on the heap. Nodes of other bases are boxed too, as
+    // those bases may hold this one in turn.
+    fn map(ty: &FieldTy, base_name: &str, boxed: bool) -> String {
+        match ty {
+            FieldTy::Base(_) if boxed => format!("Box<{}>", map(ty, base_name, false)),
+            FieldTy::Base(name) if *name == base_name => name.to_string(),
+            FieldTy::Base(name) => format!("super::{}::{}", name.to_lowercase(), name),
+            FieldTy::Token => "tokens::Token<'static>".to_string(),
+            FieldTy::Object => "tokens::LiteralValue<'static>".to_string(),
+            FieldTy::Option(inner) => format!("Option<{}>", map(inner, base_name, boxed)),
+            FieldTy::Vec(inner) => format!("Vec<{}>", map(inner, base_name, false)),
+            FieldTy::Rc(inner) => format!("std::rc::Rc<{}>", map(inner, base_name, false)),
+            FieldTy::Path(path) => path.to_string(),
+        }
+    }
+
+    /// Code folding `value`, of type `ty`, with the generated `Fold` traits. `None` when
+    /// there are no nodes in it to fold. What an `Rc` holds is folded into a new `Rc`, it
+    /// is cloned first when still shared.
+    pub fn fold_code(ty: &FieldTy, value: &str) -> Option<String> {
+        fold(ty, value, true)
+    }
+
+    fn fold(ty: &FieldTy, value: &str, boxed: bool) -> Option<String> {
+        match ty {
+            FieldTy::Base(name) if boxed => Some(format!(
+                "Box::new(self.fold_{}(*{}))",
+                name.to_lowercase(),
+                value
+            )),
+            FieldTy::Base(name) => Some(format!("self.fold_{}({})", name.to_lowercase(), value)),
+            FieldTy::Option(inner) => {
+                let inner = fold(inner, "v", boxed)?;
+                Some(format!("{}.map(|v| {})", value, inner))
+            }
+            FieldTy::Vec(inner) => {
+                let inner = fold(inner, "v", false)?;
+                Some(format!(
+                    "{}.into_iter().map(|v| {}).collect()",
+                    value, inner
+                ))
+            }
+            FieldTy::Rc(inner) => {
+                let unwrapped = format!("std::rc::Rc::unwrap_or_clone({})", value);
+                let inner = fold(inner, &unwrapped, false)?;
+                Some(format!("std::rc::Rc::new({})", inner))
+            }
+            FieldTy::Token | FieldTy::Object | FieldTy::Path(_) => None,
+        }
+    }
+
+    /// Statement walking the nodes in `value`, a field of type `ty`. `None` when there are
+    /// no nodes in it.
+    pub fn walk_code(ty: &FieldTy, value: &str) -> Option<String> {
+        walk(ty, value, false)
+    }
+
+    // `is_ref` tells whether `value` is already a reference rather than a place.
+    fn walk(ty: &FieldTy, value: &str, is_ref: bool) -> Option<String> {
+        let reference = if is_ref {
+            value.to_string()
+        } else {
+            format!("&{}", value)
+        };
+
+        match ty {
+            FieldTy::Base(name) => Some(format!(
+                "walker.walk_{}({});",
+                name.to_lowercase(),
+                reference
+            )),
+            FieldTy::Option(inner) => {
+                let inner = walk(inner, "v", true)?;
+                Some(format!("if let Some(v) = {} {{ {} }}", reference, inner))
+            }
+            FieldTy::Vec(inner) => {
+                let inner = walk(inner, "v", true)?;
+                Some(format!("for v in {} {{ {} }}", reference, inner))
+            }
+            // a reference to an `Rc` of a node coerces to a reference to the node
+            FieldTy::Rc(inner) if matches!(**inner, FieldTy::Base(_)) => walk(inner, value, is_ref),
+            FieldTy::Rc(inner) if is_ref => walk(inner, &format!("&**{}", value), true),
+            FieldTy::Rc(inner) => walk(inner, &format!("&*{}", value), true),
+            FieldTy::Token | FieldTy::Object | FieldTy::Path(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // bases holding each other and sharing a node name
+    const CYCLIC: &str = "
+base Expr {
+    Lambda   : List<Token> params, Rc<List<Stmt>> body
+    Function : Token name, Expr e
+}
+base Stmt {
+    Expression : Expr expression
+    Function   : Token name
+}
+";
+
+    #[test]
+    fn traits_cover_every_base() {
+        let grammar = grammar::parse(CYCLIC).unwrap();
+        let mut out = vec![];
+        define_inline_mods(&mut out, &grammar).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(out.matches("pub trait Fold").count(), 1);
+        assert_eq!(out.matches("pub trait Walker").count(), 1);
+        assert!(out.contains("fn walk_function_expr(&mut self, function: &expr::Function)"));
+        assert!(out.contains("fn walk_function_stmt(&mut self, function: &stmt::Function)"));
+        assert!(out.contains("fn walk_expression_stmt(&mut self, expression: &stmt::Expression)"));
+        assert!(out.contains("fn fold_lambda_expr(&mut self, lambda: expr::Lambda) -> expr::Expr"));
+        assert!(out.contains("pub expression: Box<super::expr::Expr>,"));
+        assert!(out.contains("expression: Box::new(self.fold_expr(*expression.expression))"));
+    }
+}